@@ -3,13 +3,15 @@ use crate::water::build_ground_mesh;
 use bevy::prelude::*;
 use bevy_mod_raycast::{DefaultRaycastingPlugin, RayCastSource};
 use nalgebra::RealField;
-use water_sim::{PreferredSolver, Solver};
+use water_sim::{PreferredSolver, Solver, Tracer};
 pub struct BrushPlugin;
 impl Plugin for BrushPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugin(DefaultRaycastingPlugin::<GroundMarker>::default())
             .add_system_set(
-                SystemSet::on_update(GameState::Sandbox).with_system(build_ground_system),
+                SystemSet::on_update(GameState::Sandbox)
+                    .with_system(build_ground_system)
+                    .with_system(inject_tracer_system),
             )
             .insert_resource(BrushBudget {
                 used_ground: 0.0,
@@ -86,10 +88,51 @@ pub fn build_ground_system(
             }
 
             for ground_mesh in ground_query.iter_mut() {
-                build_ground_mesh(solver.ground_h(), mesh_assets.get_mut(ground_mesh).unwrap())
+                build_ground_mesh(
+                    solver.ground_h(),
+                    solver.infiltration(),
+                    solver.layers(),
+                    mesh_assets.get_mut(ground_mesh).unwrap(),
+                )
             }
             brush_budget.used_ground += added_ground;
             info!("added ground: {} predict ground: {}", added_ground, v);
         }
     }
 }
+/// radius in cells of the disk tracer is injected over
+const TRACER_RADIUS: f32 = 3.0;
+/// amount of tracer injected per frame
+const TRACER_AMOUNT: f32 = 2.0;
+/// colour of tracer added by the player when the scenario has none
+const TRACER_COLOR: [f32; 3] = [0.9, 0.3, 0.1];
+/// system that injects tracer where the player right clicks, into the first tracer of the
+/// scenario or a new one
+pub fn inject_tracer_system(
+    mouse_input: Res<Input<MouseButton>>,
+    ray_cast_iter: Query<&RayCastSource<GroundMarker>>,
+    mut solver_query: Query<(&Transform, &mut PreferredSolver), With<WaterMarker>>,
+) {
+    if !mouse_input.pressed(MouseButton::Right) {
+        return;
+    }
+    let intersect_position = ray_cast_iter
+        .iter()
+        .filter_map(|s| s.intersect_top())
+        .map(|(_a, b)| b.position())
+        .next();
+    if let Some(pos) = intersect_position {
+        for (trans, mut solver) in solver_query.iter_mut() {
+            let p = get_water_position(pos, trans);
+            if solver.tracers().is_empty() {
+                let dimensions = nalgebra::Vector2::new(solver.dim_x(), solver.dim_y());
+                solver.set_tracers(vec![Tracer::new(dimensions, 0.05, 0.0, TRACER_COLOR)]);
+            }
+            solver.tracers_mut()[0].inject(
+                nalgebra::Vector2::new(p.x, p.z),
+                TRACER_RADIUS,
+                TRACER_AMOUNT,
+            );
+        }
+    }
+}
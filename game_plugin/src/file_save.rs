@@ -19,7 +19,7 @@ fn save_native(data: &[u8]) {
     use std::fs::File;
     use std::io::prelude::*;
     if let Some(path) = rfd::FileDialog::new()
-        .add_filter("numpy", &["npy"])
+        .add_filter("numpy archive", &["npz"])
         .save_file()
     {
         info!("choice: {:#?}", path);
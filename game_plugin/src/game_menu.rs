@@ -184,6 +184,8 @@ pub struct PlayTexture;
 pub struct ShowSpeed;
 #[derive(Component)]
 pub struct AddBoxButton;
+#[derive(Component)]
+pub struct AddBodyButton;
 /// Marks Show Velocities button
 #[derive(Component)]
 pub struct ShowVelocities;
@@ -1,9 +1,9 @@
 use crate::prelude::{
-    aabb_barrier_from_transform, build_barrier, despawn_gui, AABBMaterial, AddBoxButton,
-    GameEntity, GameMenu, GameState, GuiState, LeaveButton, LeaveText, PauseButton, PauseTexture,
-    PlayButton, PlayTexture, SaveWaterButton, ShowSpeed, ShowVelocities, ShowWater, SolveInfoLabel,
-    SolveInfoVec, SpeedDirection, ViscocityChange, WaterMarker, GUI_STYLE, MAX_WATER_SPEED,
-    WATER_SIZE,
+    aabb_barrier_from_transform, add_body, build_barrier, despawn_gui, AABBMaterial, AddBodyButton,
+    AddBoxButton, GameEntity, GameMenu, GameState, GuiState, LeaveButton, LeaveText, PauseButton,
+    PauseTexture, PlayButton, PlayTexture, SaveWaterButton, ShowSpeed, ShowVelocities, ShowWater,
+    SolveInfoLabel, SolveInfoVec, SpeedDirection, ViscocityChange, WaterMarker, GUI_STYLE,
+    MAX_WATER_SPEED, WATER_SIZE,
 };
 use bevy::prelude::*;
 use nalgebra::Vector2;
@@ -33,7 +33,8 @@ impl Plugin for GuiRunner {
                     .with_system(solve_info)
                     .with_system(leave_button)
                     .with_system(save_water)
-                    .with_system(add_box_button),
+                    .with_system(add_box_button)
+                    .with_system(add_body_button),
             )
             .add_system_set(
                 SystemSet::on_exit(self.active_state)
@@ -227,6 +228,39 @@ pub fn build_sidebar(
                         })
                         .insert(GameEntity);
                 });
+            parent
+                .spawn_bundle(ButtonBundle {
+                    style: Style {
+                        margin: UiRect::all(Val::Px(5.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: UiColor(GUI_STYLE.button_normal_color),
+                    ..Default::default()
+                })
+                .insert(GameEntity)
+                .insert(AddBodyButton)
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                align_self: AlignSelf::Center,
+                                margin: UiRect::all(Val::Px(5.0)),
+                                ..Default::default()
+                            },
+                            text: Text::from_section(
+                                "Add Body",
+                                TextStyle {
+                                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                    font_size: 30.0,
+                                    color: GUI_STYLE.button_text_color,
+                                },
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(GameEntity);
+                });
             #[cfg(feature = "native")]
             parent
                 .spawn_bundle(ButtonBundle {
@@ -635,7 +669,7 @@ fn save_water(
                 *mat = UiColor(GUI_STYLE.button_pressed_color);
 
                 if let Some(solver) = solver_query.iter().next() {
-                    crate::file_save::save(&solver.numpy_data());
+                    crate::file_save::save(&solver.checkpoint_data());
                 }
             }
             Interaction::Hovered => {
@@ -687,6 +721,34 @@ fn add_box_button(
         }
     }
 }
+fn add_body_button(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut solver_query: Query<&mut PreferredSolver, ()>,
+    mut queries: Query<(&Interaction, &mut UiColor), (Changed<Interaction>, With<AddBodyButton>)>,
+) {
+    for (interation, mut material) in queries.iter_mut() {
+        match *interation {
+            Interaction::Clicked => {
+                let mut water = if let Some(water) = solver_query.iter_mut().next() {
+                    water
+                } else {
+                    error!("failed to find water entity");
+                    return;
+                };
+                add_body(&mut commands, &mut water, &mut meshes, &mut materials);
+                *material = UiColor(GUI_STYLE.button_pressed_color);
+            }
+            Interaction::Hovered => {
+                *material = UiColor(GUI_STYLE.button_hover_color);
+            }
+            Interaction::None => {
+                *material = UiColor(GUI_STYLE.button_normal_color);
+            }
+        }
+    }
+}
 
 fn solve_info(
     mut _commands: Commands,
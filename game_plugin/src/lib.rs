@@ -27,10 +27,10 @@ use water::WaterPlugin;
 pub mod prelude {
     pub use super::brush::BrushBudget;
     pub use super::game_menu::{
-        dep_ButtonMaterial, AddBoxButton, GameEntity, GameMenu, GuiState, GuiStyle, LeaveButton,
-        LeaveText, PauseButton, PauseTexture, PlayButton, PlayTexture, SaveWaterButton, ShowSpeed,
-        ShowVelocities, ShowWater, SolveInfoLabel, SpeedDirection, ViscocityChange, GUI_STYLE,
-        MAX_WATER_SPEED,
+        dep_ButtonMaterial, AddBodyButton, AddBoxButton, GameEntity, GameMenu, GuiState, GuiStyle,
+        LeaveButton, LeaveText, PauseButton, PauseTexture, PlayButton, PlayTexture,
+        SaveWaterButton, ShowSpeed, ShowVelocities, ShowWater, SolveInfoLabel, SpeedDirection,
+        ViscocityChange, GUI_STYLE, MAX_WATER_SPEED,
     };
     pub use super::gui::{build_play_menu, GuiRunner};
     pub use super::loading::FontAssets;
@@ -43,6 +43,7 @@ pub mod prelude {
     pub use super::player::CameraLabel;
     pub use super::water::{
         aabb::{aabb_barrier_from_transform, build_barrier, AABBMaterial},
+        bodies::add_body,
         build_water_mesh_system, get_water_position, AABBBarrier, GroundMarker, InitialConditions,
         SolveInfo, SolveInfoVec, WaterMarker, WaterPlugin, WaterRunPlugin, WATER_SIZE,
     };
@@ -50,10 +50,11 @@ impl MissionScenario for TsunamiScenario {
                 Grid::from_fn(|x, y| ground(x, y), dimensions),
                 Vec::new(),
                 SolverBoundaryConditions {
-                    x_plus: ocean,
+                    x_plus: ocean.clone(),
                     x_minus: ocean,
-                    y_plus: reflect,
+                    y_plus: reflect.clone(),
                     y_minus: reflect,
+                    segments: Vec::new(),
                 },
             ),
             BrushBudget {
@@ -68,9 +69,7 @@ impl MissionScenario for TsunamiScenario {
     fn get_win_state(&self, solver: &PreferredSolver, budget: &BrushBudget) -> WinState {
         let loose_vol = 100.0f32;
         let water_height = solver.water_h();
-        let vol = (200..300)
-            .flat_map(|x| (0..50).map(move |y| water_height.get(x, y)))
-            .fold(0.0, |acc, x| acc + x);
+        let vol = water_height.window(200..300, 0..50).sum::<f32>();
         if loose_vol < vol {
             WinState::Lost
         } else {
@@ -100,7 +99,7 @@ impl MissionScenario for Canal {
             PreferredSolver::new(
                 Grid::from_fn(|x, y| (ocean_level - g(x, y)).max(0.0), dimensions),
                 Grid::from_fn(g, dimensions),
-                vec![Source {
+                vec![Source::Pulse {
                     center: Vector2::new(50.0, 100.0),
                     radius: 5.0,
                     height: 1.0,
@@ -111,6 +110,7 @@ impl MissionScenario for Canal {
                     x_minus: BoundaryConditions::Ocean { level: ocean_level },
                     y_plus: BoundaryConditions::Ocean { level: ocean_level },
                     y_minus: BoundaryConditions::Ocean { level: ocean_level },
+                    segments: Vec::new(),
                 },
             ),
             BrushBudget {
@@ -6,8 +6,13 @@ use bevy::{
     render::{mesh::Indices, render_resource::PrimitiveTopology},
 };
 use bevy_mod_raycast::RayCastMesh;
-pub use water_sim::{get_conditions, AABBBarrier, PreferredSolver, SolveInfo, Solver};
+pub use water_sim::{
+    get_conditions, AABBBarrier, Infiltration, Layers, PreferredSolver, SolveInfo, Solver,
+    Temperature, Tracer,
+};
 pub mod aabb;
+pub mod bodies;
+mod particles;
 use aabb::AABBMaterial;
 //pub use finite_solver::FiniteSolver;
 mod uv_show;
@@ -59,18 +64,62 @@ impl Plugin for WaterRunPlugin {
                 .after(WaterLabel::InsertAABBMaterial)
                 .with_system(water_simulation)
                 .with_system(show_water)
-                .with_system(aabb::aabb_transform),
+                .with_system(aabb::aabb_transform)
+                .with_system(aabb::edit_barrier)
+                .with_system(aabb::moving_barrier_transform)
+                .with_system(bodies::body_transform_system)
+                .with_system(particles::show_particles),
+        );
+    }
+}
+/// concentration of tracer at which water takes on the tracer colour fully
+const TRACER_FULL_CONCENTRATION: f32 = 0.5;
+/// degrees from the reference temperature at which water takes on the warm or cold colour fully
+const TEMPERATURE_RANGE: f32 = 20.0;
+const WARM_COLOR: [f32; 3] = [1.0, 0.35, 0.2];
+const COLD_COLOR: [f32; 3] = [0.5, 0.8, 1.0];
+/// colour multiplier of water at point, tinted by its temperature then towards the colour of
+/// each tracer in it
+fn water_color(
+    water: &water_sim::Grid<f32>,
+    tracers: &[Tracer],
+    temperature: Option<&Temperature>,
+    x: usize,
+    y: usize,
+) -> [f32; 4] {
+    let mut color = [1.0, 1.0, 1.0];
+    let mut tint = |tint_color: [f32; 3], amount: f32| {
+        for (channel, target) in color.iter_mut().zip(tint_color) {
+            *channel += (target - *channel) * amount.clamp(0.0, 1.0);
+        }
+    };
+    if let Some(temperature) = temperature {
+        let difference = temperature.temperature(water, x, y) - temperature.reference;
+        if difference > 0.0 {
+            tint(WARM_COLOR, difference / TEMPERATURE_RANGE);
+        } else {
+            tint(COLD_COLOR, -difference / TEMPERATURE_RANGE);
+        }
+    }
+    for tracer in tracers.iter() {
+        tint(
+            tracer.color,
+            tracer.concentration(water, x, y) / TRACER_FULL_CONCENTRATION,
         );
     }
+    [color[0], color[1], color[2], 1.0]
 }
 pub fn build_water_mesh(
     water: &water_sim::Grid<f32>,
     ground: &water_sim::Grid<f32>,
+    tracers: &[Tracer],
+    temperature: Option<&Temperature>,
     mesh: &mut Mesh,
 ) {
     let mut position = vec![];
     let mut normals = vec![];
     let mut uvs = vec![];
+    let mut colors = vec![];
     let mut indicies = vec![];
     const WATER_TOLERANCE: f32 = 0.0001;
 
@@ -123,6 +172,13 @@ pub fn build_water_mesh(
             position.push([x0_y1.x, x0_y1.y, x0_y1.z]);
             normals.push([triangle1_normal.x, triangle1_normal.y, triangle1_normal.z]);
             uvs.push([0.0, 1.0]);
+
+            colors.push(water_color(water, tracers, temperature, x, y));
+            colors.push(water_color(water, tracers, temperature, x, y + 1));
+            colors.push(water_color(water, tracers, temperature, x + 1, y));
+            colors.push(water_color(water, tracers, temperature, x + 1, y + 1));
+            colors.push(water_color(water, tracers, temperature, x + 1, y));
+            colors.push(water_color(water, tracers, temperature, x, y + 1));
             let delta_x0_y0 = water.get(x, y);
             let delta_x1_y0 = water.get(x + 1, y);
             let delta_x1_y1 = water.get(x + 1, y + 1);
@@ -152,12 +208,40 @@ pub fn build_water_mesh(
     mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, position);
     mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
     mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
     mesh.set_indices(Some(Indices::U32(indicies)));
 }
-pub fn build_ground_mesh(water: &water_sim::Grid<f32>, mesh: &mut Mesh) {
+/// how much saturated soil is darkened
+const WET_SOIL_DARKENING: f32 = 0.6;
+/// colour multiplier of ground at point, tinted by the exposed layer and wet soil is darker
+fn ground_color(
+    infiltration: Option<&Infiltration>,
+    layers: Option<&Layers>,
+    x: usize,
+    y: usize,
+) -> [f32; 4] {
+    let shade = if let Some(infiltration) = infiltration {
+        1.0 - WET_SOIL_DARKENING * infiltration.saturation(x, y)
+    } else {
+        1.0
+    };
+    let [r, g, b] = if let Some(layers) = layers {
+        layers.exposed_material(x, y).color
+    } else {
+        [1.0, 1.0, 1.0]
+    };
+    [r * shade, g * shade, b * shade, 1.0]
+}
+pub fn build_ground_mesh(
+    water: &water_sim::Grid<f32>,
+    infiltration: Option<&Infiltration>,
+    layers: Option<&Layers>,
+    mesh: &mut Mesh,
+) {
     let mut position = vec![];
     let mut normals = vec![];
     let mut uvs = vec![];
+    let mut colors = vec![];
     for x in 0..water.x() - 1 {
         for y in 0..water.y() - 1 {
             let x0_y0 = Vector3::new(
@@ -211,6 +295,13 @@ pub fn build_ground_mesh(water: &water_sim::Grid<f32>, mesh: &mut Mesh) {
             position.push([x0_y1.x, x0_y1.y, x0_y1.z]);
             normals.push([triangle1_normal.x, triangle1_normal.y, triangle1_normal.z]);
             uvs.push([0.0, 1.0]);
+
+            colors.push(ground_color(infiltration, layers, x, y));
+            colors.push(ground_color(infiltration, layers, x, y + 1));
+            colors.push(ground_color(infiltration, layers, x + 1, y));
+            colors.push(ground_color(infiltration, layers, x + 1, y + 1));
+            colors.push(ground_color(infiltration, layers, x + 1, y));
+            colors.push(ground_color(infiltration, layers, x, y + 1));
         }
     }
 
@@ -218,6 +309,7 @@ pub fn build_ground_mesh(water: &water_sim::Grid<f32>, mesh: &mut Mesh) {
     mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, position);
     mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
     mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
     mesh.set_indices(Some(Indices::U32(indicies)));
 }
 
@@ -241,11 +333,34 @@ pub fn build_water_mesh_system(
     let info: Vec<SolveInfo> = vec![];
     let mean_h = water.mean_height();
     let water_dimensions = Vector2::new(water.water_h().x(), water.water_h().y());
+    let moving_barriers = water.moving_barriers();
+
+    for (index, body) in water.bodies().iter().enumerate() {
+        bodies::build_body(
+            &mut commands,
+            index,
+            body,
+            &mut meshes,
+            &mut materials,
+            water_dimensions.x,
+        );
+    }
 
     let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
     let mut ground_mesh = Mesh::new(PrimitiveTopology::TriangleList);
-    build_ground_mesh(&water.ground_h(), &mut ground_mesh);
-    build_water_mesh(water.water_h(), water.ground_h(), &mut mesh);
+    build_ground_mesh(
+        &water.ground_h(),
+        water.infiltration(),
+        water.layers(),
+        &mut ground_mesh,
+    );
+    build_water_mesh(
+        water.water_h(),
+        water.ground_h(),
+        water.tracers(),
+        water.temperature(),
+        &mut mesh,
+    );
     commands
         .spawn_bundle(PbrBundle::default())
         .insert(GameEntity)
@@ -272,6 +387,7 @@ pub fn build_water_mesh_system(
                 .insert(GameEntity)
                 .insert(RayCastMesh::<GroundMarker>::default())
                 .insert(GroundMarker);
+            particles::spawn_particle_mesh(parent, transform, &mut meshes, &mut materials);
         });
     /*
     commands
@@ -299,6 +415,17 @@ pub fn build_water_mesh_system(
         });
 
      */
+    for (index, barrier) in moving_barriers.iter().enumerate() {
+        aabb::build_moving_barrier(
+            &mut commands,
+            index,
+            barrier,
+            &aabb_material,
+            &mut meshes,
+            mean_h,
+            water_dimensions,
+        );
+    }
     for barrier in barriers.drain(..) {
         aabb::build_barrier(
             &mut commands,
@@ -365,12 +492,18 @@ fn water_simulation(
         let ground = water.ground_h();
 
         let mut mesh = mesh_assets.get_mut(mesh).unwrap();
-        build_water_mesh(&heights, ground, &mut mesh);
+        build_water_mesh(
+            &heights,
+            ground,
+            water.tracers(),
+            water.temperature(),
+            &mut mesh,
+        );
         info.data = out_info;
     }
     for mesh in queries.p1().iter_mut() {
         let mut mesh = mesh_assets.get_mut(mesh).unwrap();
-        build_ground_mesh(water.ground_h(), mesh);
+        build_ground_mesh(water.ground_h(), water.infiltration(), water.layers(), mesh);
     }
 }
 /// Handles showing velocities and water
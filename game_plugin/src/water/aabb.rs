@@ -1,8 +1,13 @@
 use super::{HEIGHT_MULTIPLIER, WATER_SIZE};
 use crate::prelude::GameEntity;
 use bevy::prelude::*;
+use bevy_mod_picking::Selection;
 use nalgebra::Vector2;
 use water_sim::{AABBBarrier, PreferredSolver, Solver};
+/// change of porosity for each key press when editing a barrier
+const POROSITY_STEP: f32 = 0.1;
+/// change of crest height for each key press when editing a barrier
+const CREST_STEP: f32 = 0.1;
 pub struct AABBMaterial {
     pub material: Handle<StandardMaterial>,
 }
@@ -22,8 +27,19 @@ pub fn aabb_barrier_from_transform(transform: Transform, water: &PreferredSolver
     AABBBarrier {
         bottom_left: Vector2::new(lower.x as i32, lower.z as i32),
         top_right: Vector2::new(upper.x as i32, upper.z as i32),
+        ..Default::default()
     }
 }
+/// height of center of cube of a barrier without a crest built at mean water height mean_h
+fn rest_translation(mean_h: f32, water_dimensions: Vector2<usize>) -> f32 {
+    let scaling = WATER_SIZE / water_dimensions.x as f32;
+    mean_h * HEIGHT_MULTIPLIER * scaling
+}
+/// height of center of cube with height scale_y whose top is at the crest of the barrier
+fn crest_translation(crest: f32, scale_y: f32, water_dimensions: Vector2<usize>) -> f32 {
+    let scaling = WATER_SIZE / water_dimensions.x as f32;
+    crest * HEIGHT_MULTIPLIER * scaling - scale_y / 2.0
+}
 
 fn build_cube_from_aabb(
     aabb: &water_sim::AABBBarrier,
@@ -39,13 +55,16 @@ fn build_cube_from_aabb(
 
     let mut transform = Transform::from_translation(Vec3::new(
         center_x,
-        y * HEIGHT_MULTIPLIER * scaling,
+        rest_translation(y, water_dimensions),
         center_z,
     ));
 
     let scale_xz = aabb.top_right - aabb.bottom_left;
     let scale_xz = scaling * Vector2::new(scale_xz.x as f32, scale_xz.y as f32);
     transform.scale = Vec3::new(scale_xz.x, 2.0, scale_xz.y);
+    if let Some(crest) = aabb.crest {
+        transform.translation.y = crest_translation(crest, transform.scale.y, water_dimensions);
+    }
 
     PbrBundle {
         mesh,
@@ -54,6 +73,11 @@ fn build_cube_from_aabb(
         ..Default::default()
     }
 }
+/// height the cube of a barrier was built at, it goes back there when its crest is removed
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BarrierRestHeight {
+    pub y: f32,
+}
 pub fn build_barrier(
     commands: &mut Commands,
     aabb: water_sim::AABBBarrier,
@@ -73,8 +97,63 @@ pub fn build_barrier(
         .insert_bundle(bevy_mod_picking::PickableBundle::default())
         .insert(bevy_transform_gizmo::GizmoTransformable)
         .insert(GameEntity)
+        .insert(BarrierRestHeight {
+            y: rest_translation(mean_h, water_dimensions),
+        })
         .insert(aabb);
 }
+/// marks the cube showing the moving barrier at index in the solver
+#[derive(Component, Clone, Copy, Debug)]
+pub struct MovingBarrierMarker {
+    pub index: usize,
+}
+/// spawns the cube showing a moving barrier, it follows its script so it is not pickable
+pub fn build_moving_barrier(
+    commands: &mut Commands,
+    index: usize,
+    aabb: &AABBBarrier,
+    material: &AABBMaterial,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    mean_h: f32,
+    water_dimensions: Vector2<usize>,
+) {
+    commands
+        .spawn_bundle(build_cube_from_aabb(
+            aabb,
+            material.material.clone(),
+            meshes,
+            mean_h,
+            water_dimensions,
+        ))
+        .insert(GameEntity)
+        .insert(MovingBarrierMarker { index });
+}
+/// moves cubes of moving barriers to where the barriers are at the current step
+pub fn moving_barrier_transform(
+    water_query: Query<&PreferredSolver, ()>,
+    mut barrier_query: Query<(&MovingBarrierMarker, &mut Transform)>,
+) {
+    let water = if let Some(water) = water_query.iter().next() {
+        water
+    } else {
+        return;
+    };
+    let water_dimensions = Vector2::new(water.water_h().x(), water.water_h().y());
+    let scaling = WATER_SIZE / water_dimensions.x as f32;
+    let barriers = water.moving_barriers();
+    for (marker, mut transform) in barrier_query.iter_mut() {
+        let aabb = if let Some(aabb) = barriers.get(marker.index) {
+            aabb
+        } else {
+            continue;
+        };
+        transform.translation.x = scaling * (aabb.top_right.x + aabb.bottom_left.x) as f32 / 2.0;
+        transform.translation.z = scaling * (aabb.top_right.y + aabb.bottom_left.y) as f32 / 2.0;
+        if let Some(crest) = aabb.crest {
+            transform.translation.y = crest_translation(crest, transform.scale.y, water_dimensions);
+        }
+    }
+}
 pub fn aabb_transform(
     water_query: Query<&PreferredSolver, ()>,
     mut box_query: Query<(&mut AABBBarrier, &Transform), Changed<Transform>>,
@@ -94,3 +173,80 @@ pub fn aabb_transform(
         aabb.top_right = Vector2::new(upper.x as i32, upper.z as i32);
     }
 }
+/// mean height of the water surface over the cells of the barrier, None if it covers no cells
+fn mean_surface(aabb: &AABBBarrier, water: &PreferredSolver) -> Option<f32> {
+    let (dim_x, dim_y) = (water.water_h().x() as i32, water.water_h().y() as i32);
+    let (start_x, end_x) = (aabb.bottom_left.x.max(0), (aabb.top_right.x + 1).min(dim_x));
+    let (start_y, end_y) = (aabb.bottom_left.y.max(0), (aabb.top_right.y + 1).min(dim_y));
+    if start_x >= end_x || start_y >= end_y {
+        return None;
+    }
+    let x_range = start_x as usize..end_x as usize;
+    let y_range = start_y as usize..end_y as usize;
+    let cells = (x_range.len() * y_range.len()) as f32;
+    let water_h = water
+        .water_h()
+        .window(x_range.clone(), y_range.clone())
+        .sum::<f32>();
+    let ground_h = water.ground_h().window(x_range, y_range).sum::<f32>();
+    Some((water_h + ground_h) / cells)
+}
+/// Edits selected barriers, `[` and `]` lower and raise porosity, `-` and `=` lower and raise
+/// the crest and backspace removes the crest so that water can not flow over
+pub fn edit_barrier(
+    keyboard: Res<Input<KeyCode>>,
+    water_query: Query<&PreferredSolver, ()>,
+    mut box_query: Query<(
+        &Selection,
+        &mut AABBBarrier,
+        &mut Transform,
+        &BarrierRestHeight,
+    )>,
+) {
+    let water = if let Some(water) = water_query.iter().next() {
+        water
+    } else {
+        return;
+    };
+    let water_dimensions = Vector2::new(water.water_h().x(), water.water_h().y());
+    for (selection, mut aabb, mut transform, rest_height) in box_query.iter_mut() {
+        if !selection.selected() {
+            continue;
+        }
+        let mut barrier = *aabb;
+        if keyboard.just_pressed(KeyCode::LBracket) {
+            barrier.porosity = (barrier.porosity - POROSITY_STEP).max(0.0);
+        }
+        if keyboard.just_pressed(KeyCode::RBracket) {
+            barrier.porosity = (barrier.porosity + POROSITY_STEP).min(1.0);
+        }
+        let (lower, raise) = (
+            keyboard.just_pressed(KeyCode::Minus),
+            keyboard.just_pressed(KeyCode::Equals),
+        );
+        if lower || raise {
+            // crest starts at the mean water surface over the barrier when first set, as crests
+            // are compared against water plus ground height
+            let crest = barrier.crest.unwrap_or_else(|| {
+                mean_surface(&barrier, water)
+                    .unwrap_or_else(|| water.mean_height() + water.ground_h().mean().unwrap_or(0.0))
+            });
+            let step = if raise { CREST_STEP } else { -CREST_STEP };
+            barrier.crest = Some(crest + step);
+        }
+        if keyboard.just_pressed(KeyCode::Back) {
+            barrier.crest = None;
+        }
+        if barrier != *aabb {
+            info!(
+                "barrier porosity: {:.1} crest: {:?}",
+                barrier.porosity, barrier.crest
+            );
+            transform.translation.y = match barrier.crest {
+                Some(crest) => crest_translation(crest, transform.scale.y, water_dimensions),
+                None => rest_height.y,
+            };
+            *aabb = barrier;
+        }
+    }
+}
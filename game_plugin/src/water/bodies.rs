@@ -0,0 +1,95 @@
+use super::{HEIGHT_MULTIPLIER, WATER_SIZE};
+use crate::prelude::GameEntity;
+use bevy::prelude::*;
+use bevy_mod_picking::Selection;
+use nalgebra::Vector2;
+use water_sim::{FloatingBody, PreferredSolver, Solver};
+const BODY_COLOR: Color = Color::rgb(0.55, 0.35, 0.15);
+/// marks the cube showing the floating body at index in the solver
+#[derive(Component, Clone, Copy, Debug)]
+pub struct BodyMarker {
+    pub index: usize,
+}
+/// transform of a unit cube covering body
+fn body_transform(body: &FloatingBody, water_x: usize) -> Transform {
+    let scaling = WATER_SIZE / water_x as f32;
+    let mut transform = Transform::from_translation(
+        scaling
+            * Vec3::new(
+                body.position.x,
+                (body.elevation + body.height / 2.0) * HEIGHT_MULTIPLIER,
+                body.position.y,
+            ),
+    );
+    transform.scale = scaling
+        * Vec3::new(
+            2.0 * body.half_size.x,
+            body.height * HEIGHT_MULTIPLIER,
+            2.0 * body.half_size.y,
+        );
+    transform
+}
+/// spawns a pickable cube for the body at index in the solver
+pub fn build_body(
+    commands: &mut Commands,
+    index: usize,
+    body: &FloatingBody,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    water_x: usize,
+) {
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(shape::Cube::new(1.0).into()),
+            material: materials.add(BODY_COLOR.into()),
+            transform: body_transform(body, water_x),
+            ..Default::default()
+        })
+        .insert_bundle(bevy_mod_picking::PickableBundle::default())
+        .insert(bevy_transform_gizmo::GizmoTransformable)
+        .insert(GameEntity)
+        .insert(BodyMarker { index });
+}
+/// Adds a body floating in the middle of the water and spawns its cube
+pub fn add_body(
+    commands: &mut Commands,
+    water: &mut PreferredSolver,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let center = Vector2::new(water.dim_x() as f32, water.dim_y() as f32) / 2.0;
+    let size = (water.dim_x().min(water.dim_y()) as f32 / 20.0).max(1.0);
+    let mut body = FloatingBody::new(center, Vector2::new(size, size / 2.0), 0.5, 0.5);
+    body.settle(water.water_h(), water.ground_h());
+    let index = water.bodies().len();
+    water.bodies_mut().push(body);
+    build_body(commands, index, &body, meshes, materials, water.dim_x());
+}
+/// Moves cubes to follow their bodies. Selected bodies are held by the user instead, they
+/// follow the cube and rest on the water where it is dropped
+pub fn body_transform_system(
+    mut water_query: Query<&mut PreferredSolver, ()>,
+    mut body_query: Query<(&BodyMarker, &Selection, &mut Transform)>,
+) {
+    let mut water = if let Some(water) = water_query.iter_mut().next() {
+        water
+    } else {
+        return;
+    };
+    let water_x = water.dim_x();
+    let scaling = water_x as f32 / WATER_SIZE;
+    for (marker, selection, mut transform) in body_query.iter_mut() {
+        let mut body = if let Some(body) = water.bodies().get(marker.index) {
+            *body
+        } else {
+            continue;
+        };
+        if selection.selected() {
+            body.position =
+                scaling * Vector2::new(transform.translation.x, transform.translation.z);
+            body.settle(water.water_h(), water.ground_h());
+            water.bodies_mut()[marker.index] = body;
+        }
+        *transform = body_transform(&body, water_x);
+    }
+}
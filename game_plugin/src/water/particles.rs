@@ -0,0 +1,109 @@
+use crate::prelude::GameEntity;
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use water_sim::{PreferredSolver, Solver};
+/// half the width of the marker drawn at each particle in cells
+const PARTICLE_SIZE: f32 = 0.3;
+/// height markers float above the water surface so they are not hidden by it
+const PARTICLE_LIFT: f32 = 0.05;
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ParticleMarker;
+/// spawns the mesh particles are drawn with, transform is the transform of the water
+pub fn spawn_particle_mesh(
+    parent: &mut ChildBuilder,
+    transform: Transform,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    build_particle_mesh(&[], &mut mesh);
+    parent
+        .spawn_bundle(PbrBundle {
+            material: materials.add(StandardMaterial {
+                base_color: Color::rgb(1.0, 0.9, 0.2),
+                unlit: true,
+                ..Default::default()
+            }),
+            transform,
+            mesh: meshes.add(mesh),
+            ..Default::default()
+        })
+        .insert(GameEntity)
+        .insert(ParticleMarker);
+}
+/// builds a small octahedron at each point
+fn build_particle_mesh(points: &[Vec3], mesh: &mut Mesh) {
+    const CORNERS: [[f32; 3]; 6] = [
+        [1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0],
+        [0.0, -1.0, 0.0],
+        [0.0, 0.0, 1.0],
+        [0.0, 0.0, -1.0],
+    ];
+    const FACES: [[u32; 3]; 8] = [
+        [0, 2, 4],
+        [4, 2, 1],
+        [1, 2, 5],
+        [5, 2, 0],
+        [4, 3, 0],
+        [1, 3, 4],
+        [5, 3, 1],
+        [0, 3, 5],
+    ];
+    let mut position = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut indices = vec![];
+    for point in points.iter() {
+        let offset = position.len() as u32;
+        for corner in CORNERS.iter() {
+            let corner = Vec3::from(*corner);
+            position.push((*point + corner * PARTICLE_SIZE).to_array());
+            normals.push(corner.to_array());
+            uvs.push([0.0, 0.0]);
+        }
+        indices.extend(FACES.iter().flatten().map(|i| offset + i));
+    }
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, position);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+}
+/// moves particle markers to where the particles are, on the water surface
+pub fn show_particles(
+    mut mesh_assets: ResMut<Assets<Mesh>>,
+    solver_query: Query<&PreferredSolver>,
+    particle_query: Query<&Handle<Mesh>, With<ParticleMarker>>,
+) {
+    let solver = if let Some(solver) = solver_query.iter().next() {
+        solver
+    } else {
+        return;
+    };
+    let points = if let Some(particles) = solver.particles() {
+        let (water, ground) = (solver.water_h(), solver.ground_h());
+        particles
+            .particles()
+            .iter()
+            .map(|particle| {
+                let x = (particle.position.x.round() as usize).min(water.x() - 1);
+                let y = (particle.position.y.round() as usize).min(water.y() - 1);
+                Vec3::new(
+                    particle.position.x,
+                    water.get(x, y) + ground.get(x, y) + PARTICLE_LIFT,
+                    particle.position.y,
+                )
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    for mesh in particle_query.iter() {
+        if let Some(mesh) = mesh_assets.get_mut(mesh) {
+            build_particle_mesh(&points, mesh);
+        }
+    }
+}
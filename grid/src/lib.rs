@@ -1,4 +1,6 @@
 mod debug_buffer;
+mod sample;
+mod tiled;
 mod vector;
 
 use nalgebra::Vector2;
@@ -7,17 +9,20 @@ use std::{
     collections::HashMap,
     fs::File,
     io::{Error as IoError, Read, Seek, Write},
+    ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Range, Sub, SubAssign},
     path::Path,
     rc::Rc,
     str::{from_utf8, Utf8Error},
 };
 
 pub use debug_buffer::DebugBuffer;
+pub use sample::Interpolation;
 use thiserror::Error;
+pub use tiled::{ActiveTiles, TiledGrid};
 pub use vector::Vector;
 #[derive(Error, Debug, Clone)]
 pub enum BoundsCheckError {
-    #[error("Invalid Index")]
+    #[error("Index ({invalid_x}, {invalid_y}) is outside of grid of size ({size_x}, {size_y})")]
     InvalidIndex {
         invalid_x: i32,
         invalid_y: i32,
@@ -44,7 +49,7 @@ impl From<std::io::Error> for FileError {
     }
 }
 use zip::{write::FileOptions as WriterFileOptions, ZipWriter};
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 pub struct Grid<T: Clone + Copy> {
     points: Vec<T>,
     x: usize,
@@ -59,18 +64,6 @@ impl<T: Clone + Copy + Default + Vector> Grid<T> {
         let _ = Self::save_several_layers_writer(&mut file, grid_layers)?;
         Ok(())
     }
-    pub fn get_checked(&self, x: i32, y: i32) -> Result<T, BoundsCheckError> {
-        if x < 0 || y < 0 || x >= self.x() as i32 || y >= self.y as i32 {
-            return Err(BoundsCheckError::InvalidIndex {
-                invalid_x: x,
-                invalid_y: y,
-                size_x: self.x() as i32,
-                size_y: self.y() as i32,
-            });
-        } else {
-            Ok(self.get(x as usize, y as usize))
-        }
-    }
     pub fn save_several_layers_writer<W: Write + Seek>(
         writer: &mut W,
         grid_layers: &[&Grid<T>],
@@ -330,65 +323,14 @@ impl<T: Clone + Copy + Default + Vector> Grid<T> {
         file.write(&data)?;
         Ok(())
     }
-    pub fn from_vec(dimensions: Vector2<usize>, points: Vec<T>) -> Self {
-        assert_eq!(dimensions.x * dimensions.y, points.len());
-        Self {
-            points,
-            x: dimensions.x,
-            y: dimensions.y,
-        }
-    }
-    /// X dimensions
-    pub fn x(&self) -> usize {
-        self.x
-    }
-    /// Y dimensions
-    pub fn y(&self) -> usize {
-        self.y
-    }
-    ///
-    pub fn get(&self, x: usize, y: usize) -> T {
-        self.points[self.y * x + y]
-    }
-    /// gets value at index or gets other value
-    pub fn get_or(&self, x: i32, y: i32, other_value: T) -> T {
-        if x >= 0 && y >= 0 {
-            let idx = self.get_idx(x as usize, y as usize);
-            if idx < self.points.len() {
-                self.points[idx]
-            } else {
-                other_value
-            }
-        } else {
-            other_value
-        }
-    }
-    /// gets idx of coords
-    fn get_idx(&self, x: usize, y: usize) -> usize {
-        self.y * x + y
-    }
-    /// gets mut unchecked
-    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut T {
-        &mut self.points[self.y * x + y]
-    }
-    /// gets points unchecked at point
+    /// gets point without converting coordinates first, panics if it is outside of the grid
     pub fn get_unchecked(&self, dim: Vector2<i64>) -> T {
         self.get(dim.x as usize, dim.y as usize)
     }
-    /// gets unchecked mut
+    /// gets mut without converting coordinates first, panics if it is outside of the grid
     pub fn get_mut_unchecked(&mut self, dim: Vector2<i64>) -> &mut T {
         self.get_mut(dim.x as usize, dim.y as usize)
     }
-    /// builds grid from function
-    pub fn from_fn<F: Fn(usize, usize) -> T>(f: F, dimensions: Vector2<usize>) -> Self {
-        let mut s = Self::from_vec(dimensions, vec![T::default(); dimensions.x * dimensions.y]);
-        for x in 0..dimensions.x {
-            for y in 0..dimensions.y {
-                *s.get_mut(x, y) = f(x, y);
-            }
-        }
-        s
-    }
     fn make_header<const SIZE: usize>(shape: [u32; SIZE]) -> Vec<u8> {
         let mut shape_str = String::new();
         for i in 0..SIZE - 1 {
@@ -426,25 +368,286 @@ impl<T: Clone + Copy + Default + Vector> Grid<T> {
         return out_data;
     }
 }
-impl<T: std::ops::Add + std::ops::AddAssign + Clone + Copy> std::ops::Add for Grid<T> {
-    type Output = Self;
-    fn add(mut self, other: Self) -> Self {
+impl<T: Clone + Copy> Grid<T> {
+    pub fn from_vec(dimensions: Vector2<usize>, points: Vec<T>) -> Self {
+        assert_eq!(dimensions.x * dimensions.y, points.len());
+        Self {
+            points,
+            x: dimensions.x,
+            y: dimensions.y,
+        }
+    }
+    /// builds grid from function
+    pub fn from_fn<F: Fn(usize, usize) -> T>(f: F, dimensions: Vector2<usize>) -> Self {
+        let points = (0..dimensions.x)
+            .flat_map(|x| (0..dimensions.y).map(move |y| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Self::from_vec(dimensions, points)
+    }
+    /// X dimensions
+    pub fn x(&self) -> usize {
+        self.x
+    }
+    /// Y dimensions
+    pub fn y(&self) -> usize {
+        self.y
+    }
+    /// gets point, panics if it is outside of the grid
+    pub fn get(&self, x: usize, y: usize) -> T {
+        self[(x, y)]
+    }
+    /// gets idx of coords
+    fn get_idx(&self, x: usize, y: usize) -> usize {
+        self.y * x + y
+    }
+    /// gets mut, panics if the point is outside of the grid
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut T {
+        &mut self[(x, y)]
+    }
+    /// whether (x, y) is inside of the grid
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.x && (y as usize) < self.y
+    }
+    /// Index of (x, y) into points. Both coordinates are checked as points past the end of a
+    /// column would otherwise land in the next column
+    fn checked_idx(&self, x: i32, y: i32) -> Result<usize, BoundsCheckError> {
+        if self.contains(x, y) {
+            Ok(self.get_idx(x as usize, y as usize))
+        } else {
+            Err(BoundsCheckError::InvalidIndex {
+                invalid_x: x,
+                invalid_y: y,
+                size_x: self.x as i32,
+                size_y: self.y as i32,
+            })
+        }
+    }
+    pub fn get_checked(&self, x: i32, y: i32) -> Result<T, BoundsCheckError> {
+        self.checked_idx(x, y).map(|idx| self.points[idx])
+    }
+    pub fn get_mut_checked(&mut self, x: i32, y: i32) -> Result<&mut T, BoundsCheckError> {
+        let idx = self.checked_idx(x, y)?;
+        Ok(&mut self.points[idx])
+    }
+    /// gets point or None if it is outside of the grid
+    pub fn try_get(&self, x: i32, y: i32) -> Option<T> {
+        self.get_checked(x, y).ok()
+    }
+    /// gets mut or None if the point is outside of the grid
+    pub fn try_get_mut(&mut self, x: i32, y: i32) -> Option<&mut T> {
+        self.get_mut_checked(x, y).ok()
+    }
+    /// gets value at index or gets other value if it is outside of the grid
+    pub fn get_or(&self, x: i32, y: i32, other_value: T) -> T {
+        self.try_get(x, y).unwrap_or(other_value)
+    }
+    /// points in the order they are stored, y changes fastest
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        self.points.iter().copied()
+    }
+    /// builds grid of the same size by applying f to each point
+    pub fn map<U: Clone + Copy, F: Fn(T) -> U>(&self, f: F) -> Grid<U> {
+        Grid {
+            points: self.points.iter().map(|p| f(*p)).collect(),
+            x: self.x,
+            y: self.y,
+        }
+    }
+    /// applies f to each point without allocating
+    pub fn map_in_place<F: Fn(T) -> T>(&mut self, f: F) {
+        for p in self.points.iter_mut() {
+            *p = f(*p);
+        }
+    }
+    /// builds grid by applying f to matching points of both grids, which must be the same size
+    pub fn zip_map<U: Clone + Copy, V: Clone + Copy, F: Fn(T, U) -> V>(
+        &self,
+        other: &Grid<U>,
+        f: F,
+    ) -> Grid<V> {
         assert_eq!(self.x, other.x);
         assert_eq!(self.y, other.y);
-        for i in 0..self.points.len() {
-            self.points[i] += other.points[i];
-        }
-        Self {
-            points: self.points,
+        Grid {
+            points: self
+                .points
+                .iter()
+                .zip(other.points.iter())
+                .map(|(a, b)| f(*a, *b))
+                .collect(),
             x: self.x,
             y: self.y,
         }
     }
+    /// combines every point into init in the order they are stored
+    pub fn fold<A, F: Fn(A, T) -> A>(&self, init: A, f: F) -> A {
+        self.points.iter().fold(init, |acc, p| f(acc, *p))
+    }
+    /// Points with x in x_range and y in y_range, x changes slowest. Panics if the window does
+    /// not fit in the grid
+    pub fn window(
+        &self,
+        x_range: Range<usize>,
+        y_range: Range<usize>,
+    ) -> impl Iterator<Item = T> + '_ {
+        assert!(x_range.end <= self.x && y_range.end <= self.y);
+        x_range.flat_map(move |x| {
+            let column = &self.points[self.y * x..self.y * (x + 1)];
+            column[y_range.start..y_range.end.max(y_range.start)]
+                .iter()
+                .copied()
+        })
+    }
+}
+impl<T: Clone + Copy> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+    /// panics if the point is outside of the grid
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        assert!(
+            x < self.x && y < self.y,
+            "index ({}, {}) is outside of grid of size ({}, {})",
+            x,
+            y,
+            self.x,
+            self.y
+        );
+        &self.points[self.get_idx(x, y)]
+    }
+}
+impl<T: Clone + Copy> IndexMut<(usize, usize)> for Grid<T> {
+    /// panics if the point is outside of the grid
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        assert!(
+            x < self.x && y < self.y,
+            "index ({}, {}) is outside of grid of size ({}, {})",
+            x,
+            y,
+            self.x,
+            self.y
+        );
+        let idx = self.get_idx(x, y);
+        &mut self.points[idx]
+    }
+}
+impl Grid<f32> {
+    pub fn sum(&self) -> f32 {
+        self.points.iter().sum()
+    }
+    /// smallest point, infinity for an empty grid
+    pub fn min(&self) -> f32 {
+        self.fold(f32::INFINITY, f32::min)
+    }
+    /// largest point, negative infinity for an empty grid
+    pub fn max(&self) -> f32 {
+        self.fold(f32::NEG_INFINITY, f32::max)
+    }
+    /// mean of the points, None for an empty grid
+    pub fn mean(&self) -> Option<f32> {
+        if self.points.is_empty() {
+            None
+        } else {
+            Some(self.sum() / self.points.len() as f32)
+        }
+    }
 }
+/// elementwise operator between grids of the same size and with a scalar on each point
+macro_rules! grid_op {
+    ($op:ident, $op_fn:ident, $assign:ident, $assign_fn:ident) => {
+        impl<T: $assign + Clone + Copy> $op for Grid<T> {
+            type Output = Self;
+            fn $op_fn(mut self, other: Self) -> Self {
+                self.$assign_fn(&other);
+                self
+            }
+        }
+        impl<T: $assign + Clone + Copy> $assign<&Grid<T>> for Grid<T> {
+            fn $assign_fn(&mut self, other: &Grid<T>) {
+                assert_eq!(self.x, other.x);
+                assert_eq!(self.y, other.y);
+                for (p, o) in self.points.iter_mut().zip(other.points.iter()) {
+                    p.$assign_fn(*o);
+                }
+            }
+        }
+        impl<T: $assign + Clone + Copy> $op<T> for Grid<T> {
+            type Output = Self;
+            fn $op_fn(mut self, scalar: T) -> Self {
+                self.$assign_fn(scalar);
+                self
+            }
+        }
+        impl<T: $assign + Clone + Copy> $assign<T> for Grid<T> {
+            fn $assign_fn(&mut self, scalar: T) {
+                for p in self.points.iter_mut() {
+                    p.$assign_fn(scalar);
+                }
+            }
+        }
+    };
+}
+grid_op!(Add, add, AddAssign, add_assign);
+grid_op!(Sub, sub, SubAssign, sub_assign);
+grid_op!(Mul, mul, MulAssign, mul_assign);
+grid_op!(Div, div, DivAssign, div_assign);
 #[cfg(test)]
 mod test {
     use super::*;
     #[test]
+    fn bounds() {
+        let mut grid = Grid::from_fn(|x, y| (10 * x + y) as f32, Vector2::new(3, 4));
+        assert_eq!(grid[(1, 2)], 12.0);
+        grid[(2, 3)] = -1.0;
+        assert_eq!(grid.get(2, 3), -1.0);
+        // past the end of a column does not wrap around into the next column
+        assert_eq!(grid.get_or(0, 4, 7.0), 7.0);
+        assert_eq!(grid.get_or(1, -1, 7.0), 7.0);
+        assert_eq!(grid.try_get(0, 4), None);
+        assert_eq!(grid.try_get(2, 0), Some(20.0));
+        assert!(grid.get_checked(3, 0).is_err());
+        assert!(!grid.contains(0, 4));
+        *grid.get_mut_checked(0, 3).unwrap() = 5.0;
+        assert_eq!(grid.get(0, 3), 5.0);
+        assert!(grid.get_mut_checked(-1, 0).is_err());
+        assert!(grid.try_get_mut(0, 4).is_none());
+    }
+    #[test]
+    #[should_panic]
+    fn get_past_column_panics() {
+        Grid::from_fn(|_, _| 0.0, Vector2::new(3, 4)).get(0, 4);
+    }
+    #[test]
+    fn arithmetic() {
+        let dimensions = Vector2::new(3, 4);
+        let a = Grid::from_fn(|x, y| (x * 4 + y) as f32, dimensions);
+        let b = Grid::from_fn(|_, _| 2.0, dimensions);
+        let sum = a.clone() + b.clone();
+        let difference = a.clone() - b.clone();
+        let product = a.clone() * b.clone();
+        assert_eq!(sum.get(2, 3), 13.0);
+        assert_eq!(difference.get(2, 3), 9.0);
+        assert_eq!(product.get(2, 3), 22.0);
+        let mut scaled = a.clone() * 0.5 + 1.0;
+        scaled -= &b;
+        assert_eq!(scaled.get(1, 2), 2.0);
+        assert_eq!(a.map(|p| p as i32 % 2).get(1, 1), 1);
+        assert_eq!(
+            a.zip_map(&b, |p, q| p > q).fold(0, |n, p| n + p as usize),
+            9
+        );
+        assert_eq!(a.sum(), 66.0);
+        assert_eq!(a.min(), 0.0);
+        assert_eq!(a.max(), 11.0);
+        assert_eq!(a.mean(), Some(5.5));
+        assert_eq!(
+            Grid::<f32>::from_vec(Vector2::new(0, 2), vec![]).mean(),
+            None
+        );
+        // rows 1 and 2 of columns 1 and 2
+        let window = a.window(1..3, 1..3).collect::<Vec<_>>();
+        assert_eq!(window, vec![5.0, 6.0, 9.0, 10.0]);
+        assert_eq!(a.window(0..3, 2..2).count(), 0);
+    }
+    #[test]
     fn save_and_load() {
         let dimensions = Vector2::new(10, 10);
         let g = Grid::from_fn(|_, _| 0.0, dimensions);
@@ -0,0 +1,144 @@
+use super::Grid;
+use nalgebra::Vector2;
+use std::ops::{Add, Mul};
+/// How values between cell centres are found
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// linear along each axis from the four nearest cells
+    Bilinear,
+    /// Catmull-Rom spline through the sixteen nearest cells, smoother than bilinear but may
+    /// overshoot next to sharp changes
+    Bicubic,
+}
+impl<T: Clone + Copy + Default + Add<Output = T> + Mul<f32, Output = T>> Grid<T> {
+    /// Value at position in cells, cell centres are at integer coordinates. Positions past
+    /// the outer cell centres take the value of the edge. Panics if the grid has no cells
+    pub fn sample(&self, position: Vector2<f32>, interpolation: Interpolation) -> T {
+        assert!(
+            self.x > 0 && self.y > 0,
+            "can not sample grid of size ({}, {}) with no cells",
+            self.x,
+            self.y
+        );
+        let x = position.x.clamp(0.0, (self.x - 1) as f32);
+        let y = position.y.clamp(0.0, (self.y - 1) as f32);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (t_x, t_y) = (x - x0 as f32, y - y0 as f32);
+        match interpolation {
+            Interpolation::Bilinear => {
+                let (x1, y1) = ((x0 + 1).min(self.x - 1), (y0 + 1).min(self.y - 1));
+                let bottom = self.get(x0, y0) * (1.0 - t_x) + self.get(x1, y0) * t_x;
+                let top = self.get(x0, y1) * (1.0 - t_x) + self.get(x1, y1) * t_x;
+                bottom * (1.0 - t_y) + top * t_y
+            }
+            Interpolation::Bicubic => {
+                let clamp = |i: usize, d: usize, dim: usize| (i + d).saturating_sub(1).min(dim - 1);
+                let (w_x, w_y) = (cubic_weights(t_x), cubic_weights(t_y));
+                let mut value = T::default();
+                for (i, w_x) in w_x.iter().enumerate() {
+                    let mut column = T::default();
+                    for (j, w_y) in w_y.iter().enumerate() {
+                        column =
+                            column + self.get(clamp(x0, i, self.x), clamp(y0, j, self.y)) * *w_y;
+                    }
+                    value = value + column * *w_x;
+                }
+                value
+            }
+        }
+    }
+    /// Grid of a different size covering the same area, cell centres of the new grid are
+    /// sampled from this grid. Use downsample to shrink grids without skipping cells
+    pub fn resize(&self, dimensions: Vector2<usize>, interpolation: Interpolation) -> Self {
+        let scale = Vector2::new(
+            self.x as f32 / dimensions.x as f32,
+            self.y as f32 / dimensions.y as f32,
+        );
+        Grid::from_fn(
+            |x, y| {
+                let position = Vector2::new(
+                    (x as f32 + 0.5) * scale.x - 0.5,
+                    (y as f32 + 0.5) * scale.y - 0.5,
+                );
+                self.sample(position, interpolation)
+            },
+            dimensions,
+        )
+    }
+    /// grid factor times larger along each axis
+    pub fn upsample(&self, factor: usize, interpolation: Interpolation) -> Self {
+        self.resize(Vector2::new(self.x, self.y) * factor, interpolation)
+    }
+    /// Grid factor times smaller along each axis where each cell is the mean of the block of
+    /// cells it covers, blocks on the far edges may be smaller
+    pub fn downsample(&self, factor: usize) -> Self {
+        assert!(factor > 0);
+        let dimensions =
+            Vector2::new(self.x, self.y).map(|dim| dim / factor + usize::from(dim % factor != 0));
+        Grid::from_fn(
+            |x, y| {
+                let x_range = x * factor..((x + 1) * factor).min(self.x);
+                let y_range = y * factor..((y + 1) * factor).min(self.y);
+                let count = x_range.len() * y_range.len();
+                self.window(x_range, y_range)
+                    .fold(T::default(), |sum, p| sum + p)
+                    * (1.0 / count as f32)
+            },
+            dimensions,
+        )
+    }
+}
+/// Catmull-Rom weights of the four points around t, where t is the distance past the second
+fn cubic_weights(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ]
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn interpolation() {
+        let dimensions = Vector2::new(5, 4);
+        let linear = Grid::from_fn(|x, y| 2.0 * x as f32 + y as f32, dimensions);
+        for interpolation in [Interpolation::Bilinear, Interpolation::Bicubic] {
+            // both are exact for linear functions away from the edges
+            let value = linear.sample(Vector2::new(1.25, 1.5), interpolation);
+            assert!((value - 4.0).abs() < 1e-5);
+            assert_eq!(linear.sample(Vector2::new(3.0, 2.0), interpolation), 8.0);
+            // held at the edges
+            assert_eq!(linear.sample(Vector2::new(-1.0, 7.0), interpolation), 3.0);
+        }
+        let velocity = Grid::from_fn(|x, _| Vector2::new(x as f32, 1.0), dimensions);
+        let sampled = velocity.sample(Vector2::new(0.5, 0.0), Interpolation::Bilinear);
+        assert_eq!(sampled, Vector2::new(0.5, 1.0));
+    }
+    #[test]
+    fn resampling() {
+        let grid = Grid::from_fn(|x, y| (x + 4 * y) as f32, Vector2::new(4, 4));
+        let small = grid.downsample(2);
+        assert_eq!((small.x(), small.y()), (2, 2));
+        assert_eq!(small.get(1, 0), 4.5);
+        assert_eq!(small.mean(), grid.mean());
+        assert_eq!(grid.downsample(3).get(1, 1), 15.0);
+        let large = small.upsample(2, Interpolation::Bilinear);
+        assert_eq!((large.x(), large.y()), (4, 4));
+        assert_eq!(large.get(1, 0), 3.0);
+        // past the outer cell centres the edge is held
+        assert_eq!(large.get(3, 0), 4.5);
+        let same = grid.resize(Vector2::new(4, 4), Interpolation::Bicubic);
+        assert_eq!(same.get(2, 3), grid.get(2, 3));
+        let empty = grid.resize(Vector2::new(0, 3), Interpolation::Bilinear);
+        assert_eq!((empty.x(), empty.y()), (0, 3));
+    }
+    #[test]
+    #[should_panic(expected = "no cells")]
+    fn sample_empty() {
+        let empty = Grid::from_fn(|_, _| 0.0, Vector2::new(0, 3));
+        empty.sample(Vector2::new(0.0, 0.0), Interpolation::Bilinear);
+    }
+}
@@ -0,0 +1,209 @@
+use nalgebra::Vector2;
+/// Grid stored as square tiles. Tiles are only allocated once they are written to, tiles that
+/// were never written or were cleared hold the fill value and take no memory
+#[derive(Clone, Debug)]
+pub struct TiledGrid<T: Clone + Copy> {
+    tiles: Vec<Option<Box<[T]>>>,
+    fill: T,
+    tile_size: usize,
+    tiles_y: usize,
+    x: usize,
+    y: usize,
+}
+impl<T: Clone + Copy> TiledGrid<T> {
+    /// grid with every point set to fill
+    pub fn new(dimensions: Vector2<usize>, tile_size: usize, fill: T) -> Self {
+        assert!(tile_size > 0);
+        let tiles = tile_count(dimensions, tile_size);
+        Self {
+            tiles: vec![None; tiles.x * tiles.y],
+            fill,
+            tile_size,
+            tiles_y: tiles.y,
+            x: dimensions.x,
+            y: dimensions.y,
+        }
+    }
+    /// X dimensions
+    pub fn x(&self) -> usize {
+        self.x
+    }
+    /// Y dimensions
+    pub fn y(&self) -> usize {
+        self.y
+    }
+    pub fn tile_size(&self) -> usize {
+        self.tile_size
+    }
+    /// index of tile and of point in tile
+    fn get_idx(&self, x: usize, y: usize) -> (usize, usize) {
+        let tile = (x / self.tile_size) * self.tiles_y + y / self.tile_size;
+        (
+            tile,
+            (x % self.tile_size) * self.tile_size + y % self.tile_size,
+        )
+    }
+    pub fn get(&self, x: usize, y: usize) -> T {
+        assert!(x < self.x && y < self.y);
+        let (tile, idx) = self.get_idx(x, y);
+        self.tiles[tile]
+            .as_ref()
+            .map_or(self.fill, |tile| tile[idx])
+    }
+    /// gets mut, allocating the tile holding the point
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut T {
+        assert!(x < self.x && y < self.y);
+        let (tile, idx) = self.get_idx(x, y);
+        let (fill, len) = (self.fill, self.tile_size * self.tile_size);
+        &mut self.tiles[tile].get_or_insert_with(|| vec![fill; len].into_boxed_slice())[idx]
+    }
+    /// sets every point of tile back to the fill value and frees it
+    pub fn clear_tile(&mut self, tile: Vector2<usize>) {
+        self.tiles[tile.x * self.tiles_y + tile.y] = None;
+    }
+    pub fn is_allocated(&self, tile: Vector2<usize>) -> bool {
+        self.tiles[tile.x * self.tiles_y + tile.y].is_some()
+    }
+    /// number of tiles holding memory
+    pub fn allocated_tiles(&self) -> usize {
+        self.tiles.iter().filter(|tile| tile.is_some()).count()
+    }
+}
+/// number of tiles along each axis needed to cover dimensions
+fn tile_count(dimensions: Vector2<usize>, tile_size: usize) -> Vector2<usize> {
+    dimensions.map(|dim| dim / tile_size + usize::from(dim % tile_size != 0))
+}
+/// Tiles of a grid that need to be simulated. A tile is active when a cell in it or in one of
+/// the eight tiles around it is wet, so water can always flow from an active tile into the
+/// next tile over
+#[derive(Clone, Debug)]
+pub struct ActiveTiles {
+    active: Vec<bool>,
+    tile_size: usize,
+    tiles: Vector2<usize>,
+    x: usize,
+    y: usize,
+}
+impl ActiveTiles {
+    /// starts with every tile active
+    pub fn new(dimensions: Vector2<usize>, tile_size: usize) -> Self {
+        assert!(tile_size > 0);
+        let tiles = tile_count(dimensions, tile_size);
+        Self {
+            active: vec![true; tiles.x * tiles.y],
+            tile_size,
+            tiles,
+            x: dimensions.x,
+            y: dimensions.y,
+        }
+    }
+    /// number of tiles along x and y
+    pub fn tiles(&self) -> Vector2<usize> {
+        self.tiles
+    }
+    pub fn is_active(&self, tile: Vector2<usize>) -> bool {
+        self.active[tile.x * self.tiles.y + tile.y]
+    }
+    /// number of active tiles
+    pub fn count(&self) -> usize {
+        self.active.iter().filter(|active| **active).count()
+    }
+    /// Finds active tiles from whether each cell is wet. wrap_x and wrap_y make tiles on
+    /// opposite edges neighbours for domains that wrap around
+    pub fn update(&mut self, is_wet: impl Fn(usize, usize) -> bool, wrap_x: bool, wrap_y: bool) {
+        let (tiles_x, tiles_y) = (self.tiles.x, self.tiles.y);
+        let mut wet = vec![false; tiles_x * tiles_y];
+        for t_x in 0..tiles_x {
+            for t_y in 0..tiles_y {
+                wet[t_x * tiles_y + t_y] = self
+                    .tile_cells(Vector2::new(t_x, t_y))
+                    .any(|(x, y)| is_wet(x, y));
+            }
+        }
+        // neighbouring tile along one axis, None past the edge unless the axis wraps
+        let step = |t: usize, d: i32, len: usize, wrap: bool| {
+            let n = t as i32 + d;
+            if n >= 0 && n < len as i32 {
+                Some(n as usize)
+            } else if wrap {
+                Some(n.rem_euclid(len as i32) as usize)
+            } else {
+                None
+            }
+        };
+        for t_x in 0..tiles_x {
+            for t_y in 0..tiles_y {
+                let mut active = false;
+                for d_x in -1..=1 {
+                    for d_y in -1..=1 {
+                        if let (Some(n_x), Some(n_y)) = (
+                            step(t_x, d_x, tiles_x, wrap_x),
+                            step(t_y, d_y, tiles_y, wrap_y),
+                        ) {
+                            active |= wet[n_x * tiles_y + n_y];
+                        }
+                    }
+                }
+                self.active[t_x * tiles_y + t_y] = active;
+            }
+        }
+    }
+    /// cells of tile, tiles on the far edges may be smaller than the tile size
+    pub fn tile_cells(&self, tile: Vector2<usize>) -> impl Iterator<Item = (usize, usize)> {
+        let (x, y) = (tile.x * self.tile_size, tile.y * self.tile_size);
+        let (end_x, end_y) = (
+            (x + self.tile_size).min(self.x),
+            (y + self.tile_size).min(self.y),
+        );
+        (x..end_x).flat_map(move |x| (y..end_y).map(move |y| (x, y)))
+    }
+    /// tiles that are not active
+    pub fn inactive_tiles(&self) -> impl Iterator<Item = Vector2<usize>> + '_ {
+        let tiles_y = self.tiles.y;
+        (0..self.active.len())
+            .filter(move |i| !self.active[*i])
+            .map(move |i| Vector2::new(i / tiles_y, i % tiles_y))
+    }
+    /// cells of every active tile
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let tiles_y = self.tiles.y;
+        (0..self.active.len())
+            .filter(move |i| self.active[*i])
+            .flat_map(move |i| self.tile_cells(Vector2::new(i / tiles_y, i % tiles_y)))
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn tiles_allocated_on_write() {
+        let mut grid = TiledGrid::new(Vector2::new(10, 7), 4, 1.0);
+        assert_eq!(grid.get(9, 6), 1.0);
+        assert_eq!(grid.allocated_tiles(), 0);
+        *grid.get_mut(9, 6) = 2.0;
+        assert_eq!(grid.get(9, 6), 2.0);
+        assert_eq!(grid.get(8, 4), 1.0);
+        assert!(grid.is_allocated(Vector2::new(2, 1)));
+        assert_eq!(grid.allocated_tiles(), 1);
+        grid.clear_tile(Vector2::new(2, 1));
+        assert_eq!(grid.get(9, 6), 1.0);
+        assert_eq!(grid.allocated_tiles(), 0);
+    }
+    #[test]
+    fn active_tiles() {
+        let mut active = ActiveTiles::new(Vector2::new(20, 10), 4);
+        assert_eq!(active.tiles(), Vector2::new(5, 3));
+        assert_eq!(active.count(), 15);
+        active.update(|x, y| x == 0 && y == 9, false, false);
+        // the wet tile in the corner and the three around it
+        assert_eq!(active.count(), 4);
+        assert!(active.is_active(Vector2::new(1, 1)));
+        assert!(!active.is_active(Vector2::new(2, 2)));
+        assert_eq!(active.cells().count(), 4 * 4 + 4 * 4 + 4 * 2 + 4 * 2);
+        assert_eq!(active.inactive_tiles().count(), 11);
+        // wrapping around to the far edge
+        active.update(|x, y| x == 0 && y == 9, true, false);
+        assert!(active.is_active(Vector2::new(4, 2)));
+        assert_eq!(active.count(), 6);
+    }
+}
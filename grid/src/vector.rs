@@ -16,6 +16,18 @@ impl Vector for f32 {
         f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
     }
 }
+/// saved as a float that is one when true
+impl Vector for bool {
+    const DIM: usize = 1;
+
+    fn to_le_bytes(&self) -> Vec<u8> {
+        f32::to_le_bytes(if *self { 1.0 } else { 0.0 }).to_vec()
+    }
+
+    fn from_le_bytes(bytes: &[u8]) -> Self {
+        f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != 0.0
+    }
+}
 impl Vector for nalgebra::Vector2<f32> {
     const DIM: usize = 2;
 
@@ -1,9 +1,14 @@
 use bevy::prelude::*;
 use nalgebra::Vector2;
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Component)]
+#[derive(Clone, Copy, PartialEq, Debug, Default, Component)]
 pub struct AABBBarrier {
     pub top_right: Vector2<i32>,
     pub bottom_left: Vector2<i32>,
+    /// fraction of the flow of open water that seeps through the barrier, zero is a solid wall
+    pub porosity: f32,
+    /// height of the top of the barrier, water above it flows over like a weir. None is taller
+    /// than any water
+    pub crest: Option<f32>,
 }
 impl AABBBarrier {
     pub fn contains_point(&self, x: i32, y: i32) -> bool {
@@ -12,4 +17,8 @@ impl AABBBarrier {
             && self.bottom_left.x <= x
             && self.bottom_left.y <= y
     }
+    /// whether no water gets through or over barrier
+    pub fn is_solid(&self) -> bool {
+        self.porosity <= 0.0 && self.crest.is_none()
+    }
 }
@@ -0,0 +1,317 @@
+use super::{source::clamp_range, AABBBarrier, Grid};
+use nalgebra::Vector2;
+/// Solid obstacle water can not flow through. A cell is blocked if its center, at the cell's
+/// integer coordinates, is inside of the obstacle
+#[derive(Clone, Debug)]
+pub enum Obstacle {
+    /// rectangle rotated counterclockwise around its center by angle in radians
+    Rectangle {
+        center: Vector2<f32>,
+        /// distance from center to the sides before rotating
+        half_size: Vector2<f32>,
+        angle: f32,
+    },
+    Circle {
+        center: Vector2<f32>,
+        radius: f32,
+    },
+    /// closed polygon through points, may be concave
+    Polygon {
+        points: Vec<Vector2<f32>>,
+    },
+    /// cells that are true are blocked, the mask starts at cell (0, 0) and cells outside of it
+    /// are open
+    Mask(Grid<bool>),
+}
+impl Obstacle {
+    /// whether point is inside of obstacle
+    pub fn contains(&self, point: Vector2<f32>) -> bool {
+        match self {
+            Self::Rectangle {
+                center,
+                half_size,
+                angle,
+            } => {
+                let offset = point - center;
+                let (sin, cos) = angle.sin_cos();
+                // rotates point back into frame of rectangle
+                let local = Vector2::new(
+                    cos * offset.x + sin * offset.y,
+                    -sin * offset.x + cos * offset.y,
+                );
+                local.x.abs() <= half_size.x && local.y.abs() <= half_size.y
+            }
+            Self::Circle { center, radius } => (point - center).norm_squared() <= radius * radius,
+            Self::Polygon { points } => {
+                // even-odd rule, counts edges crossed by a ray towards positive x
+                let mut inside = false;
+                for i in 0..points.len() {
+                    let a = points[i];
+                    let b = points[(i + 1) % points.len()];
+                    if (a.y > point.y) != (b.y > point.y) {
+                        let crossing_x = a.x + (point.y - a.y) * (b.x - a.x) / (b.y - a.y);
+                        if point.x < crossing_x {
+                            inside = !inside;
+                        }
+                    }
+                }
+                inside
+            }
+            Self::Mask(mask) => {
+                let (x, y) = (point.x.round(), point.y.round());
+                x >= 0.0
+                    && y >= 0.0
+                    && (x as usize) < mask.x()
+                    && (y as usize) < mask.y()
+                    && mask.get(x as usize, y as usize)
+            }
+        }
+    }
+    /// corners of box containing the obstacle
+    fn bounds(&self) -> (Vector2<f32>, Vector2<f32>) {
+        match self {
+            Self::Rectangle {
+                center,
+                half_size,
+                angle,
+            } => {
+                let (sin, cos) = angle.sin_cos();
+                let extent = Vector2::new(
+                    (cos * half_size.x).abs() + (sin * half_size.y).abs(),
+                    (sin * half_size.x).abs() + (cos * half_size.y).abs(),
+                );
+                (center - extent, center + extent)
+            }
+            Self::Circle { center, radius } => (
+                center - Vector2::new(*radius, *radius),
+                center + Vector2::new(*radius, *radius),
+            ),
+            Self::Polygon { points } => points.iter().fold(
+                (
+                    Vector2::new(f32::INFINITY, f32::INFINITY),
+                    Vector2::new(f32::NEG_INFINITY, f32::NEG_INFINITY),
+                ),
+                |(min, max), p| (min.zip_map(p, f32::min), max.zip_map(p, f32::max)),
+            ),
+            Self::Mask(mask) => (
+                Vector2::new(0.0, 0.0),
+                Vector2::new(mask.x() as f32 - 1.0, mask.y() as f32 - 1.0),
+            ),
+        }
+    }
+}
+/// Cells blocked by obstacles and barriers. Shapes are rasterised once when they change so
+/// that solvers only look up a cell each step
+pub(crate) struct ObstacleMask {
+    obstacles: Vec<Obstacle>,
+    /// barriers the mask was last built with
+    boxes: Vec<AABBBarrier>,
+    /// cells holding an obstacle or barrier
+    mask: Grid<bool>,
+    /// porosity of barrier in each cell, obstacles are solid
+    porosity: Grid<f32>,
+    /// crest height of barrier in each cell, infinite if water can not flow over
+    crest: Grid<f32>,
+}
+impl ObstacleMask {
+    pub fn new(dimensions: Vector2<usize>) -> Self {
+        Self {
+            obstacles: Vec::new(),
+            boxes: Vec::new(),
+            mask: Grid::from_fn(|_, _| false, dimensions),
+            porosity: Grid::from_fn(|_, _| 0.0, dimensions),
+            crest: Grid::from_fn(|_, _| f32::INFINITY, dimensions),
+        }
+    }
+    pub fn set_obstacles(&mut self, obstacles: Vec<Obstacle>) {
+        self.obstacles = obstacles;
+        self.rasterise();
+    }
+    /// rebuilds mask if boxes changed since the last update
+    pub fn update_boxes(&mut self, boxes: &[AABBBarrier]) {
+        if self.boxes != boxes {
+            self.boxes = boxes.to_vec();
+            self.rasterise();
+        }
+    }
+    fn rasterise(&mut self) {
+        let dimensions = Vector2::new(self.mask.x(), self.mask.y());
+        self.mask = Grid::from_fn(|_, _| false, dimensions);
+        let obstacles = std::mem::take(&mut self.obstacles);
+        for obstacle in obstacles.iter() {
+            let (min, max) = obstacle.bounds();
+            for x in clamp_range(min.x, max.x, dimensions.x) {
+                for y in clamp_range(min.y, max.y, dimensions.y) {
+                    if obstacle.contains(Vector2::new(x as f32, y as f32)) {
+                        self.mark(x, y, 0.0, f32::INFINITY);
+                    }
+                }
+            }
+        }
+        self.obstacles = obstacles;
+        let boxes = std::mem::take(&mut self.boxes);
+        for barrier in boxes.iter() {
+            let (min, max) = (barrier.bottom_left, barrier.top_right);
+            let crest = barrier.crest.unwrap_or(f32::INFINITY);
+            for x in clamp_range(min.x as f32, max.x as f32, dimensions.x) {
+                for y in clamp_range(min.y as f32, max.y as f32, dimensions.y) {
+                    self.mark(x, y, barrier.porosity.clamp(0.0, 1.0), crest);
+                }
+            }
+        }
+        self.boxes = boxes;
+    }
+    /// adds barrier to cell, where barriers overlap the least porous and highest is used
+    fn mark(&mut self, x: usize, y: usize, porosity: f32, crest: f32) {
+        if self.mask.get(x, y) {
+            *self.porosity.get_mut(x, y) = self.porosity.get(x, y).min(porosity);
+            *self.crest.get_mut(x, y) = self.crest.get(x, y).max(crest);
+        } else {
+            *self.mask.get_mut(x, y) = true;
+            *self.porosity.get_mut(x, y) = porosity;
+            *self.crest.get_mut(x, y) = crest;
+        }
+    }
+    /// whether no water gets through or over cell, cells outside of the domain are not blocked
+    pub fn is_blocked(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.mask.x()
+            && (y as usize) < self.mask.y()
+            && self.mask.get(x as usize, y as usize)
+            && self.porosity.get(x as usize, y as usize) <= 0.0
+            && self.crest.get(x as usize, y as usize) == f32::INFINITY
+    }
+    /// Fraction of flow let through the face between neighbouring cells a and b, water is the
+    /// depth above ground. Water below the crest seeps through at the porosity of the barrier
+    /// and the part of the upstream depth above the crest flows over freely
+    pub fn opening(
+        &self,
+        a: (usize, usize),
+        b: (usize, usize),
+        water: &Grid<f32>,
+        ground: &Grid<f32>,
+    ) -> f32 {
+        let (a_barrier, b_barrier) = (self.mask.get(a.0, a.1), self.mask.get(b.0, b.1));
+        let (porosity, crest) = match (a_barrier, b_barrier) {
+            (false, false) => return 1.0,
+            (true, false) => (self.porosity.get(a.0, a.1), self.crest.get(a.0, a.1)),
+            (false, true) => (self.porosity.get(b.0, b.1), self.crest.get(b.0, b.1)),
+            (true, true) => (
+                self.porosity.get(a.0, a.1).min(self.porosity.get(b.0, b.1)),
+                self.crest.get(a.0, a.1).max(self.crest.get(b.0, b.1)),
+            ),
+        };
+        let surface = |(x, y): (usize, usize)| water.get(x, y) + ground.get(x, y);
+        let upstream = surface(a).max(surface(b));
+        let depth = upstream - ground.get(a.0, a.1).max(ground.get(b.0, b.1));
+        if upstream <= crest || depth <= 0.0 {
+            return porosity;
+        }
+        let overtopping = ((upstream - crest) / depth).min(1.0);
+        porosity + (1.0 - porosity) * overtopping
+    }
+    pub fn mask(&self) -> &Grid<bool> {
+        &self.mask
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    fn blocked(obstacle: Obstacle, dimensions: Vector2<usize>) -> Vec<(usize, usize)> {
+        let mut mask = ObstacleMask::new(dimensions);
+        mask.set_obstacles(vec![obstacle]);
+        let mut cells = vec![];
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                if mask.mask().get(x, y) {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+    #[test]
+    fn rasterise() {
+        let dimensions = Vector2::new(20, 20);
+        let circle = blocked(
+            Obstacle::Circle {
+                center: Vector2::new(10.0, 10.0),
+                radius: 1.0,
+            },
+            dimensions,
+        );
+        assert_eq!(circle, vec![(9, 10), (10, 9), (10, 10), (10, 11), (11, 10)]);
+        // square rotated by 45 degrees is a diamond
+        let diamond = blocked(
+            Obstacle::Rectangle {
+                center: Vector2::new(10.0, 10.0),
+                half_size: Vector2::new(1.0, 1.0),
+                angle: std::f32::consts::FRAC_PI_4,
+            },
+            dimensions,
+        );
+        assert_eq!(diamond, circle);
+        // concave L shape
+        let l_shape = blocked(
+            Obstacle::Polygon {
+                points: vec![
+                    Vector2::new(1.5, 1.5),
+                    Vector2::new(4.5, 1.5),
+                    Vector2::new(4.5, 2.5),
+                    Vector2::new(2.5, 2.5),
+                    Vector2::new(2.5, 4.5),
+                    Vector2::new(1.5, 4.5),
+                ],
+            },
+            dimensions,
+        );
+        assert_eq!(l_shape, vec![(2, 2), (2, 3), (2, 4), (3, 2), (4, 2)]);
+        // partly outside of the domain
+        let mask = Grid::from_fn(|x, y| x == y, Vector2::new(30, 30));
+        assert_eq!(blocked(Obstacle::Mask(mask), dimensions).len(), 20);
+    }
+    #[test]
+    fn boxes_change_mask() {
+        let mut mask = ObstacleMask::new(Vector2::new(10, 10));
+        let barrier = AABBBarrier {
+            bottom_left: Vector2::new(2, 2),
+            top_right: Vector2::new(3, 4),
+            ..Default::default()
+        };
+        mask.update_boxes(&[barrier]);
+        assert!(mask.is_blocked(3, 4));
+        assert!(!mask.is_blocked(4, 4));
+        mask.update_boxes(&[]);
+        assert!(!mask.is_blocked(3, 4));
+        assert!(!mask.is_blocked(-1, 0));
+        assert!(!mask.is_blocked(0, 10));
+    }
+    #[test]
+    fn opening() {
+        let dimensions = Vector2::new(3, 1);
+        let ground = Grid::from_fn(|_, _| 0.0, dimensions);
+        let mut mask = ObstacleMask::new(dimensions);
+        mask.update_boxes(&[AABBBarrier {
+            bottom_left: Vector2::new(1, 0),
+            top_right: Vector2::new(1, 0),
+            porosity: 0.2,
+            crest: Some(1.5),
+        }]);
+        assert!(!mask.is_blocked(1, 0));
+        // below crest only seeps through
+        let water = Grid::from_vec(dimensions, vec![1.0, 1.0, 0.5]);
+        assert_eq!(mask.opening((1, 0), (2, 0), &water, &ground), 0.2);
+        // half of the upstream depth is above the crest
+        let water = Grid::from_vec(dimensions, vec![3.0, 3.0, 0.5]);
+        assert!((mask.opening((0, 0), (1, 0), &water, &ground) - 0.6).abs() < 1e-6);
+        let mut solid = ObstacleMask::new(dimensions);
+        solid.update_boxes(&[AABBBarrier {
+            bottom_left: Vector2::new(1, 0),
+            top_right: Vector2::new(1, 0),
+            ..Default::default()
+        }]);
+        assert!(solid.is_blocked(1, 0));
+        assert_eq!(solid.opening((0, 0), (1, 0), &water, &ground), 0.0);
+    }
+}
@@ -0,0 +1,35 @@
+use nalgebra::Vector2;
+/// Coriolis parameter used to rotate the momentum of the water.
+/// Units of f are radians per unit of simulation time, y is measured in cells
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Coriolis {
+    /// no rotation
+    #[default]
+    None,
+    /// constant coriolis parameter over the whole domain
+    Constant { f: f32 },
+    /// beta plane approximation, parameter at row y is f0 + beta * y
+    BetaPlane { f0: f32, beta: f32 },
+}
+impl Coriolis {
+    /// gets coriolis parameter at row y
+    pub fn parameter(&self, y: f32) -> f32 {
+        match self {
+            Self::None => 0.0,
+            Self::Constant { f } => *f,
+            Self::BetaPlane { f0, beta } => f0 + beta * y,
+        }
+    }
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+    /// Rotates velocity by the angle swept out in delta_t. Rotating exactly rather than adding
+    /// f * v * delta_t keeps the inertial oscillation from growing over time
+    pub fn rotate(&self, velocity: Vector2<f32>, y: f32, delta_t: f32) -> Vector2<f32> {
+        let (sin, cos) = (self.parameter(y) * delta_t).sin_cos();
+        Vector2::new(
+            velocity.x * cos + velocity.y * sin,
+            -velocity.x * sin + velocity.y * cos,
+        )
+    }
+}
@@ -0,0 +1,240 @@
+use super::{tracer::advect, Edge, Grid};
+use nalgebra::Vector2;
+/// Erosion and deposition of ground by flowing water. Water can carry sediment in proportion
+/// to its discharge, ground is picked up while the water carries less than that and settles
+/// while it carries more. Sediment is moved with the flow separately from the water, so the
+/// volume of water and the volume of ground plus sediment are each conserved
+#[derive(Clone)]
+pub struct Erosion {
+    /// volume of sediment carried per unit of discharge
+    pub capacity: f32,
+    /// fraction of the missing capacity eroded from the ground per unit time
+    pub erosion_rate: f32,
+    /// fraction of the sediment above capacity deposited per unit time
+    pub deposition_rate: f32,
+    /// Resistance of the ground in each cell from 0 to 1. Erosion is scaled by one minus the
+    /// hardness so ground with a hardness of one does not erode
+    pub hardness: Grid<f32>,
+    /// volume of sediment suspended in the water of each cell
+    sediment: Grid<f32>,
+    /// volume of sediment carried out of the domain
+    outflow: f32,
+}
+impl Erosion {
+    /// builds erosion with clear water
+    pub fn new(
+        capacity: f32,
+        erosion_rate: f32,
+        deposition_rate: f32,
+        hardness: Grid<f32>,
+    ) -> Self {
+        let dimensions = Vector2::new(hardness.x(), hardness.y());
+        Self {
+            capacity,
+            erosion_rate,
+            deposition_rate,
+            hardness,
+            sediment: Grid::from_fn(|_, _| 0.0, dimensions),
+            outflow: 0.0,
+        }
+    }
+    /// builds erosion with the same ground everywhere
+    pub fn uniform(
+        dimensions: Vector2<usize>,
+        capacity: f32,
+        erosion_rate: f32,
+        deposition_rate: f32,
+        hardness: f32,
+    ) -> Self {
+        Self::new(
+            capacity,
+            erosion_rate,
+            deposition_rate,
+            Grid::from_fn(|_, _| hardness, dimensions),
+        )
+    }
+    /// volume of sediment suspended in each cell
+    pub fn sediment(&self) -> &Grid<f32> {
+        &self.sediment
+    }
+    /// total volume of sediment suspended in the water
+    pub fn suspended(&self) -> f32 {
+        self.sediment.sum()
+    }
+    /// volume of sediment carried out of the domain so far
+    pub fn outflow(&self) -> f32 {
+        self.outflow
+    }
+    /// Exchanges ground and sediment in each cell, discharge is the magnitude of the net flux
+    /// through the cell and erodibility scales erosion of the ground exposed in the cell
+    pub(crate) fn erode(
+        &mut self,
+        ground: &mut Grid<f32>,
+        discharge: impl Fn(usize, usize) -> f32,
+        erodibility: impl Fn(usize, usize) -> f32,
+        delta_t: f32,
+    ) {
+        for x in 0..ground.x() {
+            for y in 0..ground.y() {
+                let capacity = self.capacity * discharge(x, y);
+                let sediment = self.sediment.get(x, y);
+                let rate = if capacity > sediment {
+                    let softness =
+                        (1.0 - self.hardness.get(x, y).clamp(0.0, 1.0)) * erodibility(x, y);
+                    softness * self.erosion_rate * (capacity - sediment)
+                } else {
+                    -self.deposition_rate * (sediment - capacity)
+                };
+                // no more can settle than is suspended
+                let eroded = (rate * delta_t).max(-sediment);
+                *ground.get_mut(x, y) -= eroded;
+                *self.sediment.get_mut(x, y) += eroded;
+            }
+        }
+    }
+    /// Moves sediment with the water, arguments are as in advect
+    pub(crate) fn transport(
+        &mut self,
+        water: &Grid<f32>,
+        delta_t: f32,
+        outflow: impl Fn(usize, usize, Edge) -> f32,
+        neighbour: impl Fn(usize, usize, Edge) -> Option<(usize, usize)>,
+    ) {
+        self.outflow += advect(&mut self.sediment, water, delta_t, outflow, neighbour);
+    }
+}
+/// Collapse of slopes steeper than the angle of repose. Ground slides from each cell to its
+/// lower neighbours until the drop to every neighbour is at most the height the angle of
+/// repose allows over one cell
+#[derive(Clone, Copy, Debug)]
+pub struct ThermalErosion {
+    /// steepest stable slope in radians
+    pub angle_of_repose: f32,
+    /// fraction of the height above a stable slope moved per unit time
+    pub rate: f32,
+}
+impl ThermalErosion {
+    /// largest stable height difference between neighbouring cells
+    pub fn max_drop(&self) -> f32 {
+        self.angle_of_repose.tan()
+    }
+    /// Moves ground down slopes steeper than the angle of repose, neighbour gives the cell next
+    /// to (x, y) towards edge or None if it is outside of the domain
+    pub(crate) fn apply(
+        &self,
+        ground: &mut Grid<f32>,
+        neighbour: impl Fn(usize, usize, Edge) -> Option<(usize, usize)>,
+        delta_t: f32,
+    ) {
+        let max_drop = self.max_drop();
+        let mut new_ground = ground.clone();
+        for x in 0..ground.x() {
+            for y in 0..ground.y() {
+                let steepest = slope(ground, x, y, &neighbour);
+                if steepest <= max_drop {
+                    continue;
+                }
+                let excess = Edge::ALL.map(|edge| {
+                    neighbour(x, y, edge)
+                        .map(|(n_x, n_y)| ground.get(x, y) - ground.get(n_x, n_y) - max_drop)
+                        .unwrap_or(0.0)
+                        .max(0.0)
+                });
+                let total: f32 = excess.iter().sum();
+                // moving half of the steepest excess levels it out, more would overshoot
+                let moved = (self.rate * delta_t).min(1.0) * (steepest - max_drop) / 2.0;
+                for (edge, excess) in Edge::ALL.iter().zip(excess) {
+                    if excess <= 0.0 {
+                        continue;
+                    }
+                    let (n_x, n_y) = neighbour(x, y, *edge).unwrap();
+                    let share = moved * excess / total;
+                    *new_ground.get_mut(x, y) -= share;
+                    *new_ground.get_mut(n_x, n_y) += share;
+                }
+            }
+        }
+        *ground = new_ground;
+    }
+}
+/// Largest drop from cell (x, y) to a neighbour, zero if no neighbour is lower. neighbour
+/// gives the cell next to (x, y) towards edge or None if it is outside of the domain
+pub(crate) fn slope(
+    ground: &Grid<f32>,
+    x: usize,
+    y: usize,
+    neighbour: impl Fn(usize, usize, Edge) -> Option<(usize, usize)>,
+) -> f32 {
+    Edge::ALL
+        .iter()
+        .filter_map(|edge| neighbour(x, y, *edge))
+        .map(|(n_x, n_y)| ground.get(x, y) - ground.get(n_x, n_y))
+        .fold(0.0, f32::max)
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn hardness() {
+        let dimensions = Vector2::new(2, 1);
+        let mut ground = Grid::from_fn(|_, _| 1.0, dimensions);
+        let mut erosion = Erosion::new(1.0, 0.5, 0.5, Grid::from_vec(dimensions, vec![0.0, 1.0]));
+        erosion.erode(&mut ground, |_, _| 1.0, |_, _| 1.0, 0.1);
+        assert!(ground.get(0, 0) < 1.0);
+        assert_eq!(ground.get(1, 0), 1.0);
+        assert_eq!(erosion.sediment().get(1, 0), 0.0);
+        // everything settles once the water stops
+        for _ in 0..1000 {
+            erosion.erode(&mut ground, |_, _| 0.0, |_, _| 1.0, 0.1);
+        }
+        assert!((ground.get(0, 0) - 1.0).abs() < 1e-5);
+    }
+    #[test]
+    fn transport_conserves_sediment() {
+        let dimensions = Vector2::new(10, 1);
+        let water = Grid::from_fn(|_, _| 1.0, dimensions);
+        let mut ground = Grid::from_fn(|x, _| 1.0 - x as f32 / 10.0, dimensions);
+        let mut erosion = Erosion::uniform(dimensions, 0.5, 0.2, 0.2, 0.0);
+        // water flowing towards x plus and out of the domain
+        let outflow = |_: usize, _: usize, edge: Edge| match edge {
+            Edge::XPlus => 1.0,
+            _ => 0.0,
+        };
+        let neighbour =
+            |x: usize, y: usize, edge: Edge| edge.neighbour(dimensions.x, dimensions.y, x, y);
+        let total = ground.sum();
+        for _ in 0..100 {
+            erosion.erode(&mut ground, |_, _| 1.0, |_, _| 1.0, 0.1);
+            erosion.transport(&water, 0.1, outflow, neighbour);
+            let mass = ground.sum() + erosion.suspended() + erosion.outflow();
+            assert!((mass - total).abs() < 1e-4);
+        }
+        assert!(erosion.outflow() > 0.0);
+    }
+    #[test]
+    fn cliff_collapses() {
+        let dimensions = Vector2::new(9, 9);
+        let mut ground =
+            Grid::from_fn(|x, y| if x == 4 && y == 4 { 10.0 } else { 0.0 }, dimensions);
+        let thermal = ThermalErosion {
+            angle_of_repose: std::f32::consts::FRAC_PI_4,
+            rate: 2.0,
+        };
+        let neighbour =
+            |x: usize, y: usize, edge: Edge| edge.neighbour(dimensions.x, dimensions.y, x, y);
+        let total = ground.sum();
+        for _ in 0..2000 {
+            thermal.apply(&mut ground, neighbour, 0.1);
+        }
+        assert!((ground.sum() - total).abs() < 1e-3);
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                assert!(slope(&ground, x, y, neighbour) < thermal.max_drop() + 1e-2);
+            }
+        }
+        // stable slopes are left alone
+        let mut gentle = Grid::from_fn(|x, _| x as f32 * 0.5, dimensions);
+        thermal.apply(&mut gentle, neighbour, 0.1);
+        assert_eq!(gentle.get(3, 3), 1.5);
+    }
+}
@@ -2,21 +2,17 @@
 /// Current Wierdness:
 ///  - When having circle there is interference that breaks the model  
 ///     propagating backwards from wave front
-use super::{AABBBarrier, Grid, SolveInfo, Solver, SolverBoundaryConditions, Source};
+use super::{
+    AABBBarrier, BoundaryConditions, BoundaryError, Coriolis, Edge, Erosion, FloatingBody, Grid,
+    Infiltration, Layers, MassBalance, MovingBarrier, NestedGrid, Obstacle, Particles,
+    Precipitation, SolveInfo, Solver, SolverBoundaryConditions, Source, SourceForm, Temperature,
+    ThermalErosion, Tracer, Wind,
+};
+use crate::{barrier::ObstacleMask, nested::Nest, sponge::SpongeLayer};
 use bevy::prelude::*;
+use grid::ActiveTiles;
 use nalgebra::Vector2;
 
-/// Axis aligned bounding box
-
-fn vec_contains_point(boxes: &[AABBBarrier], x: i32, y: i32) -> bool {
-    for barrier in boxes.iter() {
-        if barrier.contains_point(x, y) {
-            return true;
-        }
-    }
-    return false;
-}
-
 #[derive(Component)]
 pub struct FiniteSolver {
     /// Ground Height
@@ -31,16 +27,57 @@ pub struct FiniteSolver {
     t: u32,
     /// sources to be added at runtime
     sources: Vec<Source>,
+    /// rotation of domain
+    coriolis: Coriolis,
+    /// wind blowing over surface
+    wind: Option<Wind>,
+    /// rain and evaporation
+    precipitation: Option<Precipitation>,
+    /// soil absorbing water
+    infiltration: Option<Infiltration>,
+    /// ground moved by the flow
+    erosion: Option<Erosion>,
+    thermal_erosion: Option<ThermalErosion>,
+    layers: Option<Layers>,
+    tracers: Vec<Tracer>,
+    temperature: Option<Temperature>,
+    particles: Option<Particles>,
+    bodies: Vec<FloatingBody>,
+    moving_barriers: Vec<MovingBarrier>,
+    nest: Option<Nest<FiniteSolver>>,
+    /// water added and removed by source terms
+    mass_balance: MassBalance,
+    source_form: SourceForm,
+    boundary_conditions: SolverBoundaryConditions,
+    sponge: Option<SpongeLayer>,
+    /// cells blocked by obstacles and barriers
+    obstacles: ObstacleMask,
+    /// tiles with water in or next to them, faces of the other tiles are dry and stay still
+    active: ActiveTiles,
+}
+/// parts of the solver read by the velocity and height updates that stay the same over a
+/// timestep
+#[derive(Clone, Copy)]
+struct StepContext<'a> {
+    ground_heights: &'a Grid<f32>,
+    obstacles: &'a ObstacleMask,
+    temperature: Option<&'a Temperature>,
+    boundary_conditions: &'a SolverBoundaryConditions,
+    active: &'a ActiveTiles,
+    t: u32,
 }
 impl Solver for FiniteSolver {
     fn new(
         water: Grid<f32>,
         ground: Grid<f32>,
         sources: Vec<Source>,
-        _boundary_conditions: SolverBoundaryConditions,
+        boundary_conditions: SolverBoundaryConditions,
     ) -> Self {
         assert_eq!(water.x(), ground.x());
         assert_eq!(water.y(), ground.y());
+        boundary_conditions
+            .validate(water.x(), water.y())
+            .expect("invalid boundary conditions");
         let dim = Vector2::new(water.x(), water.y());
         Self {
             g_h: ground,
@@ -49,13 +86,46 @@ impl Solver for FiniteSolver {
             v: Grid::from_fn(|_, _| 0.0, Vector2::new(dim.x, dim.y + 1)),
             t: 0,
             sources,
+            coriolis: Coriolis::None,
+            wind: None,
+            precipitation: None,
+            infiltration: None,
+            erosion: None,
+            thermal_erosion: None,
+            layers: None,
+            tracers: Vec::new(),
+            temperature: None,
+            particles: None,
+            bodies: Vec::new(),
+            moving_barriers: Vec::new(),
+            nest: None,
+            mass_balance: MassBalance::default(),
+            source_form: SourceForm::Height,
+            sponge: SpongeLayer::new(&boundary_conditions, dim),
+            boundary_conditions,
+            obstacles: ObstacleMask::new(dim),
+            active: ActiveTiles::new(dim, Self::TILE_SIZE),
         }
     }
     /// runs water simulation and outputs water heights
     fn solve(&mut self, boxes: &[AABBBarrier]) -> (&Grid<f32>, Vec<SolveInfo>) {
         self.time_step(boxes);
+        let mut info = if self.precipitation.is_some()
+            || self.infiltration.is_some()
+            || self.source_form == SourceForm::Flux
+        {
+            self.mass_balance.solve_info(self.volume())
+        } else {
+            vec![]
+        };
+        if let Some(erosion) = &self.erosion {
+            info.push(SolveInfo {
+                name: "Sediment",
+                data: format!("{:.2}", erosion.suspended()),
+            });
+        }
 
-        (&self.h, vec![])
+        (&self.h, info)
     }
     fn water_h(&self) -> &Grid<f32> {
         &self.h
@@ -73,12 +143,125 @@ impl Solver for FiniteSolver {
     fn get_ground_mut(&mut self, x: usize, y: usize) -> &mut f32 {
         self.g_h.get_mut(x, y)
     }
+    fn set_coriolis(&mut self, coriolis: Coriolis) {
+        self.coriolis = coriolis;
+    }
+    fn set_wind(&mut self, wind: Option<Wind>) {
+        self.wind = wind;
+    }
+    fn set_precipitation(&mut self, precipitation: Option<Precipitation>) {
+        self.precipitation = precipitation;
+    }
+    fn set_infiltration(&mut self, infiltration: Option<Infiltration>) {
+        self.infiltration = infiltration;
+    }
+    fn infiltration(&self) -> Option<&Infiltration> {
+        self.infiltration.as_ref()
+    }
+    fn set_erosion(&mut self, erosion: Option<Erosion>) {
+        self.erosion = erosion;
+    }
+    fn erosion(&self) -> Option<&Erosion> {
+        self.erosion.as_ref()
+    }
+    fn set_thermal_erosion(&mut self, thermal_erosion: Option<ThermalErosion>) {
+        self.thermal_erosion = thermal_erosion;
+    }
+    fn set_layers(&mut self, layers: Option<Layers>) {
+        self.layers = layers;
+    }
+    fn layers(&self) -> Option<&Layers> {
+        self.layers.as_ref()
+    }
+    fn set_tracers(&mut self, tracers: Vec<Tracer>) {
+        self.tracers = tracers;
+    }
+    fn tracers(&self) -> &[Tracer] {
+        &self.tracers
+    }
+    fn tracers_mut(&mut self) -> &mut [Tracer] {
+        &mut self.tracers
+    }
+    fn set_temperature(&mut self, temperature: Option<Temperature>) {
+        self.temperature = temperature;
+    }
+    fn temperature(&self) -> Option<&Temperature> {
+        self.temperature.as_ref()
+    }
+    fn set_particles(&mut self, particles: Option<Particles>) {
+        self.particles = particles;
+    }
+    fn particles(&self) -> Option<&Particles> {
+        self.particles.as_ref()
+    }
+    fn particles_mut(&mut self) -> Option<&mut Particles> {
+        self.particles.as_mut()
+    }
+    fn set_bodies(&mut self, bodies: Vec<FloatingBody>) {
+        self.bodies = bodies;
+    }
+    fn bodies(&self) -> &[FloatingBody] {
+        &self.bodies
+    }
+    fn bodies_mut(&mut self) -> &mut Vec<FloatingBody> {
+        &mut self.bodies
+    }
+    fn velocity_field(&self) -> Grid<Vector2<f32>> {
+        // velocities point towards negative x and y
+        Grid::from_fn(
+            |x, y| {
+                Vector2::new(
+                    -(self.u.get(x, y) + self.u.get(x + 1, y)) / 2.0,
+                    -(self.v.get(x, y) + self.v.get(x, y + 1)) / 2.0,
+                )
+            },
+            Vector2::new(self.h.x(), self.h.y()),
+        )
+    }
+    fn mass_balance(&self) -> MassBalance {
+        self.mass_balance
+    }
+    fn set_source_form(&mut self, form: SourceForm) {
+        self.source_form = form;
+    }
+    fn set_obstacles(&mut self, obstacles: Vec<Obstacle>) {
+        self.obstacles.set_obstacles(obstacles);
+    }
+    fn obstacle_mask(&self) -> &Grid<bool> {
+        self.obstacles.mask()
+    }
+    fn set_moving_barriers(&mut self, barriers: Vec<MovingBarrier>) {
+        self.moving_barriers = barriers;
+    }
+    fn moving_barriers(&self) -> Vec<AABBBarrier> {
+        self.moving_barriers
+            .iter()
+            .map(|barrier| barrier.at(self.t))
+            .collect()
+    }
+    fn set_nested_grid(&mut self, nest: Option<NestedGrid>) -> Result<(), BoundaryError> {
+        self.nest = None;
+        self.nest = nest
+            .map(|grid| Nest::new(grid, &self.h, &self.g_h, &self.boundary_conditions))
+            .transpose()?;
+        Ok(())
+    }
+    fn nested_grid(&self) -> Option<(&NestedGrid, &Grid<f32>)> {
+        self.nest
+            .as_ref()
+            .map(|nest| (&nest.grid, nest.fine.water_h()))
+    }
 }
 impl FiniteSolver {
     const DX: f32 = 999.0;
     const DY: f32 = 999.0;
     const G: f32 = 9.81;
     const DT: f32 = 0.1;
+    const TILE_SIZE: usize = 16;
+    /// steps faces next to a moving barrier take to speed up to it
+    const BARRIER_RELAXATION_STEPS: f32 = 4.0;
+    /// largest fraction of a cell water pushed by a moving barrier crosses in a step
+    const MAX_BARRIER_COURANT: f32 = 0.25;
 
     /// output reference to h data
     pub fn h(&self) -> &Grid<f32> {
@@ -102,20 +285,47 @@ impl FiniteSolver {
     }
     /// Returns max displacement in timestep
     pub fn time_step(&mut self, barriers: &[AABBBarrier]) -> f32 {
-        for source in self.sources.iter() {
-            source.change_h(&mut self.h, self.t);
+        let mut barriers = barriers.to_vec();
+        barriers.extend(self.moving_barriers());
+        self.obstacles.update_boxes(&barriers);
+        for barrier in self.moving_barriers.iter() {
+            let obstacles = &self.obstacles;
+            barrier.displace(&mut self.h, self.t, |x, y| obstacles.is_blocked(x, y));
+            Self::push_barrier_faces(
+                barrier,
+                &self.h,
+                &mut self.u,
+                &mut self.v,
+                obstacles,
+                self.t,
+            );
+        }
+        if self.source_form == SourceForm::Height {
+            for source in self.sources.iter() {
+                source.change_h(&mut self.h, self.t, Self::DT);
+            }
+        }
+        if let Some(precipitation) = &self.precipitation {
+            precipitation.apply(&mut self.h, &mut self.mass_balance);
         }
+        if let Some(infiltration) = &mut self.infiltration {
+            infiltration.apply(&mut self.h, &mut self.mass_balance);
+        }
+        self.boundary_conditions
+            .apply_inflow(&mut self.h, self.t, Self::DT);
+        self.update_active_tiles();
         let mut u_half = self.u.clone();
         let mut v_half = self.v.clone();
+        let context = StepContext {
+            ground_heights: &self.g_h,
+            obstacles: &self.obstacles,
+            temperature: self.temperature.as_ref(),
+            boundary_conditions: &self.boundary_conditions,
+            active: &self.active,
+            t: self.t,
+        };
 
-        Self::update_velocity(
-            &self.h,
-            &self.g_h,
-            &mut u_half,
-            &mut v_half,
-            Self::DT / 2.0,
-            barriers,
-        );
+        Self::update_velocity(&self.h, &mut u_half, &mut v_half, Self::DT / 2.0, &context);
         let mut half_h = self.h.clone();
         Self::update_heights(
             &self.h,
@@ -123,129 +333,825 @@ impl FiniteSolver {
             &self.u,
             &self.v,
             Self::DT / 2.0,
-            barriers,
+            &context,
         );
 
-        Self::update_velocity(
-            &half_h,
-            &self.g_h,
+        Self::update_velocity(&half_h, &mut self.u, &mut self.v, Self::DT, &context);
+        Self::apply_coriolis(
             &mut self.u,
             &mut self.v,
+            &self.coriolis,
+            &self.boundary_conditions,
             Self::DT,
-            barriers,
         );
+        if let Some(wind) = &self.wind {
+            Self::apply_wind(
+                &half_h,
+                &mut self.u,
+                &mut self.v,
+                wind,
+                self.t,
+                Self::DT,
+                &self.obstacles,
+            );
+        }
+        if let Some(erosion) = &mut self.erosion {
+            Self::apply_erosion(
+                erosion,
+                self.layers.as_ref(),
+                &half_h,
+                &mut self.g_h,
+                &self.u,
+                &self.v,
+                &self.boundary_conditions,
+            );
+        }
+        if let Some(thermal_erosion) = &self.thermal_erosion {
+            let boundary_conditions = &self.boundary_conditions;
+            let (dim_x, dim_y) = (self.g_h.x(), self.g_h.y());
+            thermal_erosion.apply(
+                &mut self.g_h,
+                |x, y, edge| boundary_conditions.neighbour(edge, dim_x, dim_y, x, y),
+                Self::DT,
+            );
+        }
+        if let Some(layers) = &mut self.layers {
+            layers.sync(&self.g_h);
+        }
+        Self::apply_tracers(
+            &mut self.tracers,
+            self.temperature.as_mut(),
+            &half_h,
+            &self.u,
+            &self.v,
+            &self.boundary_conditions,
+        );
+        // water crossing into the nested grid, measured the same way as in the height update
+        let exchange = self.nest.as_ref().map(|nest| {
+            Self::nest_exchange(
+                nest,
+                &half_h,
+                &self.u,
+                &self.v,
+                &self.obstacles,
+                &self.boundary_conditions,
+            )
+        });
+        // ground may have moved since the velocity update
+        let context = StepContext {
+            ground_heights: &self.g_h,
+            obstacles: &self.obstacles,
+            temperature: self.temperature.as_ref(),
+            boundary_conditions: &self.boundary_conditions,
+            active: &self.active,
+            t: self.t,
+        };
+        let max_delta =
+            Self::update_heights(&half_h, &mut self.h, &self.u, &self.v, Self::DT, &context);
+        if self.source_form == SourceForm::Flux {
+            self.mass_balance.sources +=
+                Source::apply_flux(&self.sources, &mut self.h, self.t, Self::DT);
+        }
+        if let Some(sponge) = &self.sponge {
+            Self::apply_sponge(sponge, &mut self.h, &self.g_h, &mut self.u, &mut self.v);
+        }
+        if let (Some(nest), Some(exchange)) = (&mut self.nest, exchange) {
+            nest.advance(
+                &barriers,
+                &exchange,
+                &mut self.h,
+                &self.boundary_conditions,
+                |fine| &mut fine.h,
+            );
+            Self::restrict_nest_velocity(nest, &mut self.u, &mut self.v);
+        }
+        self.apply_particles();
+        self.apply_bodies();
         self.t += 1;
-        Self::update_heights(&half_h, &mut self.h, &self.u, &self.v, Self::DT, barriers)
+        max_delta
+    }
+    /// Finds the tiles with water in or next to them and stops the faces of the other tiles.
+    /// Faces between dry cells are still in the velocity update, so this gives the same results
+    /// as stepping every cell
+    fn update_active_tiles(&mut self) {
+        let (dim_x, dim_y) = (self.h.x(), self.h.y());
+        let wrap_x = self.boundary_conditions.wraps(Edge::XMinus, dim_x, dim_y);
+        let wrap_y = self.boundary_conditions.wraps(Edge::YMinus, dim_x, dim_y);
+        let h = &self.h;
+        self.active.update(|x, y| h.get(x, y) > 0.0, wrap_x, wrap_y);
+        for tile in self.active.inactive_tiles() {
+            for (x, y) in self.active.tile_cells(tile) {
+                *self.u.get_mut(x, y) = 0.0;
+                *self.u.get_mut(x + 1, y) = 0.0;
+                *self.v.get_mut(x, y) = 0.0;
+                *self.v.get_mut(x, y + 1) = 0.0;
+            }
+        }
+    }
+    /// Speeds up the face on the far side of each cell a moving barrier moves towards so the
+    /// water there is carried along with the barrier. Barriers move far faster than the waves
+    /// they make, so the speed is capped at the speed of waves in the cell and at a Courant
+    /// limit, and the face is relaxed towards it over a few steps as setting it outright drives
+    /// the scheme unstable. Speeds are in cells per second like in the height update
+    fn push_barrier_faces(
+        barrier: &MovingBarrier,
+        h: &Grid<f32>,
+        u: &mut Grid<f32>,
+        v: &mut Grid<f32>,
+        obstacles: &ObstacleMask,
+        t: u32,
+    ) {
+        for ((x, y), edge, speed) in
+            barrier.pushed_cells(t, h.x(), h.y(), |x, y| obstacles.is_blocked(x, y))
+        {
+            let cell_size = match edge {
+                Edge::XPlus | Edge::XMinus => Self::DX,
+                Edge::YPlus | Edge::YMinus => Self::DY,
+            };
+            let wave_speed = (Self::G * h.get(x, y).max(0.0) / cell_size).sqrt();
+            let speed = (speed / Self::DT)
+                .min(wave_speed)
+                .min(Self::MAX_BARRIER_COURANT / Self::DT);
+            // velocities point towards negative x and y
+            let (face, direction) = match edge {
+                Edge::XPlus => (u.get_mut(x + 1, y), -1.0),
+                Edge::XMinus => (u.get_mut(x, y), 1.0),
+                Edge::YPlus => (v.get_mut(x, y + 1), -1.0),
+                Edge::YMinus => (v.get_mut(x, y), 1.0),
+            };
+            // water already moving faster than the barrier is left alone
+            if *face * direction < speed {
+                *face += (direction * speed - *face) / Self::BARRIER_RELAXATION_STEPS;
+            }
+        }
+    }
+    /// Flux leaving cell (x, y) through the face towards edge. Depth at faces is the mean of the
+    /// cells on either side like in the height update
+    fn face_outflow(
+        h: &Grid<f32>,
+        u: &Grid<f32>,
+        v: &Grid<f32>,
+        boundary_conditions: &SolverBoundaryConditions,
+        x: usize,
+        y: usize,
+        edge: Edge,
+    ) -> f32 {
+        let face_depth = boundary_conditions
+            .neighbour(edge, h.x(), h.y(), x, y)
+            .map(|(n_x, n_y)| (h.get(x, y) + h.get(n_x, n_y)) / 2.0)
+            .unwrap_or_else(|| h.get(x, y));
+        // velocities point towards negative x and y
+        match edge {
+            Edge::XMinus => u.get(x, y) * face_depth,
+            Edge::XPlus => -u.get(x + 1, y) * face_depth,
+            Edge::YMinus => v.get(x, y) * face_depth,
+            Edge::YPlus => -v.get(x, y + 1) * face_depth,
+        }
+    }
+    /// erodes ground and moves sediment with the flux through each face
+    fn apply_erosion(
+        erosion: &mut Erosion,
+        layers: Option<&Layers>,
+        h: &Grid<f32>,
+        g_h: &mut Grid<f32>,
+        u: &Grid<f32>,
+        v: &Grid<f32>,
+        boundary_conditions: &SolverBoundaryConditions,
+    ) {
+        let (dim_x, dim_y) = (h.x(), h.y());
+        erosion.erode(
+            g_h,
+            |x, y| {
+                let velocity = Vector2::new(
+                    (u.get(x, y) + u.get(x + 1, y)) / 2.0,
+                    (v.get(x, y) + v.get(x, y + 1)) / 2.0,
+                );
+                velocity.norm() * h.get(x, y)
+            },
+            |x, y| layers.map_or(1.0, |layers| layers.erodibility(x, y)),
+            Self::DT,
+        );
+        erosion.transport(
+            h,
+            Self::DT,
+            |x, y, edge| Self::face_outflow(h, u, v, boundary_conditions, x, y, edge),
+            |x, y, edge| boundary_conditions.neighbour(edge, dim_x, dim_y, x, y),
+        );
+    }
+    /// moves tracers and heat with the flux through each face
+    fn apply_tracers(
+        tracers: &mut [Tracer],
+        temperature: Option<&mut Temperature>,
+        h: &Grid<f32>,
+        u: &Grid<f32>,
+        v: &Grid<f32>,
+        boundary_conditions: &SolverBoundaryConditions,
+    ) {
+        let (dim_x, dim_y) = (h.x(), h.y());
+        let outflow = |x, y, edge| Self::face_outflow(h, u, v, boundary_conditions, x, y, edge);
+        let neighbour = |x, y, edge| boundary_conditions.neighbour(edge, dim_x, dim_y, x, y);
+        for tracer in tracers.iter_mut() {
+            tracer.apply(h, Self::DT, outflow, neighbour);
+        }
+        if let Some(temperature) = temperature {
+            temperature.transport(h, Self::DT, outflow, neighbour);
+        }
+    }
+    /// volume entering each cell covered by the nested grid through its faces with the rest of
+    /// the domain over one step, faces next to obstacles are closed
+    fn nest_exchange(
+        nest: &Nest<FiniteSolver>,
+        h: &Grid<f32>,
+        u: &Grid<f32>,
+        v: &Grid<f32>,
+        obstacles: &ObstacleMask,
+        boundary_conditions: &SolverBoundaryConditions,
+    ) -> Vec<((usize, usize), Edge, f32)> {
+        let (dim_x, dim_y) = (h.x(), h.y());
+        nest.grid
+            .interface(boundary_conditions, dim_x, dim_y)
+            .into_iter()
+            .map(|((x, y), edge)| {
+                let open = boundary_conditions
+                    .neighbour(edge, dim_x, dim_y, x, y)
+                    .is_some_and(|(n_x, n_y)| {
+                        !obstacles.is_blocked(x as i32, y as i32)
+                            && !obstacles.is_blocked(n_x as i32, n_y as i32)
+                    });
+                let volume = if open {
+                    -Self::DT * Self::face_outflow(h, u, v, boundary_conditions, x, y, edge)
+                } else {
+                    0.0
+                };
+                ((x, y), edge, volume)
+            })
+            .collect()
+    }
+    /// sets velocities at faces between cells covered by the nested grid to the mean of the
+    /// fine faces along them
+    fn restrict_nest_velocity(nest: &Nest<FiniteSolver>, u: &mut Grid<f32>, v: &mut Grid<f32>) {
+        let grid = &nest.grid;
+        let r = grid.ratio;
+        for x in 1..grid.size.x {
+            for y in 0..grid.size.y {
+                let sum = (0..r)
+                    .map(|i| nest.fine.u.get(x * r, y * r + i))
+                    .sum::<f32>();
+                *u.get_mut(grid.origin.x + x, grid.origin.y + y) = sum / r as f32;
+            }
+        }
+        for x in 0..grid.size.x {
+            for y in 1..grid.size.y {
+                let sum = (0..r)
+                    .map(|i| nest.fine.v.get(x * r + i, y * r))
+                    .sum::<f32>();
+                *v.get_mut(grid.origin.x + x, grid.origin.y + y) = sum / r as f32;
+            }
+        }
+    }
+    /// moves particles through the flow
+    fn apply_particles(&mut self) {
+        if self.particles.is_none() {
+            return;
+        }
+        let velocity = self.velocity_field();
+        let obstacles = &self.obstacles;
+        if let Some(particles) = &mut self.particles {
+            particles.advance(&velocity, Self::DT, |x, y| obstacles.is_blocked(x, y));
+        }
+    }
+    /// moves floating bodies and drags the velocity at the faces of the water they push back on
+    fn apply_bodies(&mut self) {
+        if self.bodies.is_empty() {
+            return;
+        }
+        let velocity = self.velocity_field();
+        let obstacles = &self.obstacles;
+        for body in self.bodies.iter_mut() {
+            let reaction = body.step(&self.h, &self.g_h, &velocity, Self::DX, Self::DT, |x, y| {
+                obstacles.is_blocked(x, y)
+            });
+            // velocities point towards negative x and y, faces on the boundary are left to the
+            // boundary conditions
+            for ((x, y), acceleration) in reaction {
+                for u_x in [x, x + 1]
+                    .into_iter()
+                    .filter(|u_x| *u_x > 0 && *u_x < self.h.x())
+                {
+                    *self.u.get_mut(u_x, y) -= Self::DT * acceleration.x;
+                }
+                for v_y in [y, y + 1]
+                    .into_iter()
+                    .filter(|v_y| *v_y > 0 && *v_y < self.h.y())
+                {
+                    *self.v.get_mut(x, v_y) -= Self::DT * acceleration.y;
+                }
+            }
+        }
+    }
+    /// damps velocities and surface inside of sponge layers, faces use the mean damping of the
+    /// cells on either side
+    fn apply_sponge(
+        sponge: &SpongeLayer,
+        h: &mut Grid<f32>,
+        g_h: &Grid<f32>,
+        u: &mut Grid<f32>,
+        v: &mut Grid<f32>,
+    ) {
+        for x in 0..u.x() {
+            for y in 0..u.y() {
+                let cell_x = x.min(h.x() - 1);
+                let damping = (sponge.damping(x.max(1) - 1, y, Self::DT)
+                    + sponge.damping(cell_x, y, Self::DT))
+                    / 2.0;
+                *u.get_mut(x, y) *= damping;
+            }
+        }
+        for x in 0..v.x() {
+            for y in 0..v.y() {
+                let cell_y = y.min(h.y() - 1);
+                let damping = (sponge.damping(x, y.max(1) - 1, Self::DT)
+                    + sponge.damping(x, cell_y, Self::DT))
+                    / 2.0;
+                *v.get_mut(x, y) *= damping;
+            }
+        }
+        sponge.damp_surface(h, g_h, Self::DT);
     }
     fn update_velocity(
         heights: &Grid<f32>,
-        ground_heights: &Grid<f32>,
         u: &mut Grid<f32>,
         v: &mut Grid<f32>,
         delta_t: f32,
-        boxes: &[AABBBarrier],
+        context: &StepContext,
     ) {
-        for x in 0..heights.x() + 1 {
-            for y in 0..heights.y() + 1 {
-                //handling u
-                if y < heights.y() {
-                    if x == 0
-                        || x == heights.x()
-                        || vec_contains_point(&boxes, x as i32, y as i32)
-                        || vec_contains_point(&boxes, x as i32 - 1, y as i32)
-                    {
-                        *u.get_mut(x, y) = 0.0;
+        let StepContext {
+            ground_heights,
+            obstacles,
+            temperature,
+            boundary_conditions,
+            active,
+            t,
+        } = *context;
+        // difference in pressure head driving flow from cell a to cell b
+        let head = |a: (usize, usize), b: (usize, usize)| match temperature {
+            Some(temperature) => temperature.head_difference(a, b, heights, ground_heights),
+            None => {
+                (heights.get(a.0, a.1) + ground_heights.get(a.0, a.1))
+                    - (heights.get(b.0, b.1) + ground_heights.get(b.0, b.1))
+            }
+        };
+        // no water flows between dry cells
+        let dry = |a: (usize, usize), b: (usize, usize)| {
+            heights.get(a.0, a.1) <= 0.0 && heights.get(b.0, b.1) <= 0.0
+        };
+        // faces on the lower sides of cell (x, y), the faces on the upper edges of the domain
+        // are updated with x or y one past the last cell
+        let mut update_faces = |x: usize, y: usize| {
+            //handling u
+            if y < heights.y() && (x == 0 || x == heights.x()) {
+                let (condition, cell_x, direction) = if x == 0 {
+                    (boundary_conditions.at(Edge::XMinus, y), 0, 1.0)
+                } else {
+                    (boundary_conditions.at(Edge::XPlus, y), x - 1, -1.0)
+                };
+                *u.get_mut(x, y) = if let BoundaryConditions::Periodic = condition {
+                    // face is shared by the last and first cell
+                    let last = heights.x() - 1;
+                    if dry((0, y), (last, y)) {
+                        0.0
                     } else {
-                        let hxn1 = heights.get(x - 1, y);
-                        let hxp1 = heights.get(x, y);
-                        let gh_xn1 = ground_heights.get(x - 1, y);
-                        let gh_xp1 = ground_heights.get(x, y);
-
-                        *u.get_mut(x, y) +=
-                            Self::G * (delta_t / Self::DX) * ((hxp1 + gh_xp1) - (hxn1 + gh_xn1));
+                        u.get(x, y) + Self::G * (delta_t / Self::DX) * head((0, y), (last, y))
                     }
-                }
-                if x < heights.x() {
-                    if y == 0
-                        || y == heights.y()
-                        || vec_contains_point(&boxes, x as i32, y as i32)
-                        || vec_contains_point(&boxes, x as i32, y as i32 - 1)
-                    {
-                        *v.get_mut(x, y) = 0.0;
-                    } else {
-                        let hyn1 = heights.get(x, y - 1);
-                        let hyp1 = heights.get(x, y);
+                } else {
+                    Self::boundary_velocity(
+                        u.get(x, y),
+                        condition,
+                        heights.get(cell_x, y),
+                        ground_heights.get(cell_x, y),
+                        delta_t / Self::DX,
+                        direction,
+                        t,
+                    )
+                };
+            } else if y < heights.y() && dry((x - 1, y), (x, y)) {
+                *u.get_mut(x, y) = 0.0;
+            } else if y < heights.y() {
+                // faces next to barriers are narrowed to the opening of the barrier
+                let opening = obstacles.opening((x - 1, y), (x, y), heights, ground_heights);
 
-                        let gh_yn1 = ground_heights.get(x, y - 1);
-                        let gh_yp1 = ground_heights.get(x, y);
-                        *v.get_mut(x, y) +=
-                            Self::G * (delta_t / Self::DY) * ((hyp1 + gh_yp1) - (hyn1 + gh_yn1));
+                *u.get_mut(x, y) = opening
+                    * (u.get(x, y) + Self::G * (delta_t / Self::DX) * head((x, y), (x - 1, y)));
+            }
+            if x < heights.x() && (y == 0 || y == heights.y()) {
+                let (condition, cell_y, direction) = if y == 0 {
+                    (boundary_conditions.at(Edge::YMinus, x), 0, 1.0)
+                } else {
+                    (boundary_conditions.at(Edge::YPlus, x), y - 1, -1.0)
+                };
+                *v.get_mut(x, y) = if let BoundaryConditions::Periodic = condition {
+                    let last = heights.y() - 1;
+                    if dry((x, 0), (x, last)) {
+                        0.0
+                    } else {
+                        v.get(x, y) + Self::G * (delta_t / Self::DY) * head((x, 0), (x, last))
                     }
+                } else {
+                    Self::boundary_velocity(
+                        v.get(x, y),
+                        condition,
+                        heights.get(x, cell_y),
+                        ground_heights.get(x, cell_y),
+                        delta_t / Self::DY,
+                        direction,
+                        t,
+                    )
+                };
+            } else if x < heights.x() && dry((x, y - 1), (x, y)) {
+                *v.get_mut(x, y) = 0.0;
+            } else if x < heights.x() {
+                let opening = obstacles.opening((x, y - 1), (x, y), heights, ground_heights);
+
+                *v.get_mut(x, y) = opening
+                    * (v.get(x, y) + Self::G * (delta_t / Self::DY) * head((x, y), (x, y - 1)));
+            }
+        };
+        let (dim_x, dim_y) = (heights.x(), heights.y());
+        for (x, y) in active.cells() {
+            update_faces(x, y);
+            if x == dim_x - 1 {
+                update_faces(dim_x, y);
+            }
+            if y == dim_y - 1 {
+                update_faces(x, dim_y);
+            }
+        }
+    }
+    /// Velocity through a boundary face next to a cell with water height h and ground height
+    /// g_h. Velocities point towards the lower edge so direction is 1 on lower edges and -1 on
+    /// upper edges, time_ratio is the timestep divided by the cell size
+    fn boundary_velocity(
+        velocity: f32,
+        condition: &BoundaryConditions,
+        h: f32,
+        g_h: f32,
+        time_ratio: f32,
+        direction: f32,
+        t: u32,
+    ) -> f32 {
+        // no water leaves dry cells
+        if h <= 0.0 {
+            return 0.0;
+        }
+        let outside_level = match condition {
+            BoundaryConditions::Reflect | BoundaryConditions::Inflow { .. } => return 0.0,
+            BoundaryConditions::FreeOutflow => {
+                // discharge at critical depth divided by depth at face
+                let critical_depth = 2.0 * h / 3.0;
+                return direction * critical_depth * (Self::G * critical_depth).sqrt() / h;
+            }
+            BoundaryConditions::Absorb => g_h,
+            BoundaryConditions::Ocean { level } | BoundaryConditions::Sponge { level, .. } => {
+                *level
+            }
+            BoundaryConditions::Tide { level } => level.value(t),
+            BoundaryConditions::Periodic => {
+                unreachable!("periodic faces are updated from the cells they couple")
+            }
+        };
+        velocity + direction * Self::G * time_ratio * ((h + g_h) - outside_level)
+    }
+    /// rotates u and v by the coriolis parameter. Velocities are staggered so the other component
+    /// is averaged from the four nearest faces. Faces on the edge of the domain are walls and
+    /// are skipped unless the edge is periodic, where the faces on opposite edges are the same
+    /// face and the cells on the other side of it wrap around
+    fn apply_coriolis(
+        u: &mut Grid<f32>,
+        v: &mut Grid<f32>,
+        coriolis: &Coriolis,
+        boundary_conditions: &SolverBoundaryConditions,
+        delta_t: f32,
+    ) {
+        if coriolis.is_none() {
+            return;
+        }
+        let dim_x = v.x();
+        let dim_y = u.y();
+        let u_old = u.clone();
+        let v_old = v.clone();
+        for x in 0..dim_x {
+            for y in 0..dim_y {
+                let left = match boundary_conditions.neighbour(Edge::XMinus, dim_x, dim_y, x, y) {
+                    Some((left, _)) => left,
+                    None => continue,
+                };
+                let v_avg = (v_old.get(left, y)
+                    + v_old.get(x, y)
+                    + v_old.get(left, y + 1)
+                    + v_old.get(x, y + 1))
+                    / 4.0;
+                let rotated =
+                    coriolis.rotate(Vector2::new(u_old.get(x, y), v_avg), y as f32, delta_t);
+                *u.get_mut(x, y) = rotated.x;
+                if x == 0 {
+                    *u.get_mut(dim_x, y) = rotated.x;
+                }
+            }
+        }
+        for x in 0..dim_x {
+            for y in 0..dim_y {
+                let below = match boundary_conditions.neighbour(Edge::YMinus, dim_x, dim_y, x, y) {
+                    Some((_, below)) => below,
+                    None => continue,
+                };
+                let u_avg = (u_old.get(x, below)
+                    + u_old.get(x + 1, below)
+                    + u_old.get(x, y)
+                    + u_old.get(x + 1, y))
+                    / 4.0;
+                let rotated = coriolis.rotate(
+                    Vector2::new(u_avg, v_old.get(x, y)),
+                    y as f32 - 0.5,
+                    delta_t,
+                );
+                *v.get_mut(x, y) = rotated.y;
+                if y == 0 {
+                    *v.get_mut(x, dim_y) = rotated.y;
                 }
             }
         }
     }
+    /// adds surface stress from wind. u and v point towards negative x and y so the
+    /// acceleration is subtracted
+    fn apply_wind(
+        heights: &Grid<f32>,
+        u: &mut Grid<f32>,
+        v: &mut Grid<f32>,
+        wind: &Wind,
+        t: u32,
+        delta_t: f32,
+        obstacles: &ObstacleMask,
+    ) {
+        for x in 1..heights.x() {
+            for y in 0..heights.y() {
+                if obstacles.is_blocked(x as i32, y as i32)
+                    || obstacles.is_blocked(x as i32 - 1, y as i32)
+                {
+                    continue;
+                }
+                let depth = (heights.get(x - 1, y) + heights.get(x, y)) / 2.0;
+                let velocity = (wind.velocity(x - 1, y, t) + wind.velocity(x, y, t)) / 2.0;
+                *u.get_mut(x, y) -= delta_t * wind.acceleration(velocity, depth).x;
+            }
+        }
+        for x in 0..heights.x() {
+            for y in 1..heights.y() {
+                if obstacles.is_blocked(x as i32, y as i32)
+                    || obstacles.is_blocked(x as i32, y as i32 - 1)
+                {
+                    continue;
+                }
+                let depth = (heights.get(x, y - 1) + heights.get(x, y)) / 2.0;
+                let velocity = (wind.velocity(x, y - 1, t) + wind.velocity(x, y, t)) / 2.0;
+                *v.get_mut(x, y) -= delta_t * wind.acceleration(velocity, depth).y;
+            }
+        }
+    }
     fn update_heights(
         h: &Grid<f32>,
         h_apply: &mut Grid<f32>,
         u: &Grid<f32>,
         v: &Grid<f32>,
         delta_t: f32,
-        boxes: &[AABBBarrier],
+        context: &StepContext,
     ) -> f32 {
+        let StepContext {
+            obstacles,
+            boundary_conditions,
+            active,
+            ..
+        } = *context;
         let mut max_delta = 0.0;
-        for x in 0..h.x() {
-            for y in 0..h.y() {
-                let un1 = u.get(x, y);
-                let up1 = u.get(x + 1, y);
-                let vn1 = v.get(x, y);
-                let vp1 = v.get(x, y + 1);
-
-                let hxn1 = if x >= 1 { h.get(x - 1, y) } else { h.get(x, y) };
-                let hxp1 = if x <= h.x() - 2 {
-                    h.get(x + 1, y)
-                } else {
-                    h.get(x, y)
-                };
-
-                let hyn1 = if y >= 1 { h.get(x, y - 1) } else { h.get(x, y) };
-                let hyp1 = if y <= h.y() - 2 {
-                    h.get(x, y + 1)
-                } else {
-                    h.get(x, y)
-                };
-                let h0 = h.get(x, y);
-                let mut dx = 0.0;
-                //lower x boundry
-                if x >= 1 || !vec_contains_point(&boxes, x as i32 - 1, y as i32) {
-                    dx += un1 * (hxn1 + h0) / 2.0;
-                }
-                // upper x boundry
-                if x <= h.x() - 2 || !vec_contains_point(&boxes, x as i32 + 1, y as i32) {
-                    dx -= up1 * (hxp1 + h0) / 2.0;
-                }
+        // faces of inactive tiles are still so their heights do not change
+        for (x, y) in active.cells() {
+            if obstacles.is_blocked(x as i32, y as i32) {
+                continue;
+            }
+            let un1 = u.get(x, y);
+            let up1 = u.get(x + 1, y);
+            let vn1 = v.get(x, y);
+            let vp1 = v.get(x, y + 1);
 
-                //let dx = un1 * (hxn1 + h0) / 2.0 - up1 * (hxp1 + h0) / 2.0;
-                let mut dy = 0.0;
-                //lower y boundry
-                if y >= 1 || !vec_contains_point(&boxes, x as i32, y as i32 - 1) {
-                    dy += vn1 * (hyn1 + h0) / 2.0;
-                }
-                // upper y boundry
-                if y <= h.y() - 2 || !vec_contains_point(&boxes, x as i32, y as i32 + 1) {
-                    dy -= vp1 * (hyp1 + h0) / 2.0;
-                }
-                let delta = delta_t * (dx + dy);
-                {
-                    let h_old = h.get(x, y);
-                    if delta >= h_old {
-                        println!("use k?");
-                    }
+            let [hxp1, hxn1, hyp1, hyn1] = Edge::ALL.map(|edge| {
+                boundary_conditions
+                    .neighbour(edge, h.x(), h.y(), x, y)
+                    .map(|(n_x, n_y)| h.get(n_x, n_y))
+                    .unwrap_or_else(|| h.get(x, y))
+            });
+            let h0 = h.get(x, y);
+            // faces next to obstacles are closed
+            let open = |edge: Edge| {
+                boundary_conditions
+                    .neighbour(edge, h.x(), h.y(), x, y)
+                    .is_none_or(|(n_x, n_y)| !obstacles.is_blocked(n_x as i32, n_y as i32))
+            };
+            let mut dx = 0.0;
+            if open(Edge::XMinus) {
+                dx += un1 * (hxn1 + h0) / 2.0;
+            }
+            if open(Edge::XPlus) {
+                dx -= up1 * (hxp1 + h0) / 2.0;
+            }
+            let mut dy = 0.0;
+            if open(Edge::YMinus) {
+                dy += vn1 * (hyn1 + h0) / 2.0;
+            }
+            if open(Edge::YPlus) {
+                dy -= vp1 * (hyp1 + h0) / 2.0;
+            }
+            let delta = delta_t * (dx + dy);
+            {
+                let h_old = h.get(x, y);
+                if delta >= h_old {
+                    println!("use k?");
                 }
-                max_delta = if delta > max_delta { delta } else { max_delta };
-                *h_apply.get_mut(x, y) -= delta;
             }
+            max_delta = if delta > max_delta { delta } else { max_delta };
+            *h_apply.get_mut(x, y) -= delta;
         }
         max_delta
     }
 }
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn inertial_oscillation() {
+        let dimensions = Vector2::new(50, 50);
+        let f = 0.1;
+        let mut solver = FiniteSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        solver.set_coriolis(Coriolis::Constant { f });
+        for x in 1..dimensions.x {
+            for y in 0..dimensions.y {
+                *solver.u.get_mut(x, y) = 0.01;
+            }
+        }
+        // records steps where u at the center crosses zero going negative
+        let mut crossings = vec![];
+        let mut last_u = solver.u().get(25, 25);
+        for step in 0..1000 {
+            solver.time_step(&[]);
+            let u = solver.u().get(25, 25);
+            if last_u > 0.0 && u <= 0.0 {
+                crossings.push(step);
+            }
+            last_u = u;
+        }
+        assert_eq!(crossings.len(), 2);
+        let expected_period = 2.0 * std::f32::consts::PI / (f * FiniteSolver::DT);
+        let period = (crossings[1] - crossings[0]) as f32;
+        assert!(
+            (period - expected_period).abs() < 2.0,
+            "period: {} expected: {}",
+            period,
+            expected_period
+        );
+    }
+    #[test]
+    fn coriolis_across_periodic_seams() {
+        let dimensions = Vector2::new(10, 10);
+        let mut solver = FiniteSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::periodic(),
+        );
+        solver.set_coriolis(Coriolis::Constant { f: 0.1 });
+        solver.u = Grid::from_fn(|_, _| 0.01, Vector2::new(dimensions.x + 1, dimensions.y));
+        for _ in 0..50 {
+            solver.time_step(&[]);
+        }
+        // uniform flow turns the same everywhere, faces on the seams included
+        assert!(solver.v().get(5, 5).abs() > 1e-3);
+        for i in 0..dimensions.x {
+            assert!((solver.u().get(0, i) - solver.u().get(5, i)).abs() < 1e-6);
+            assert!((solver.v().get(i, 0) - solver.v().get(i, 5)).abs() < 1e-6);
+        }
+    }
+    #[test]
+    fn paddle_makes_waves() {
+        use crate::TimeSeries;
+        let dimensions = Vector2::new(60, 1);
+        let mut solver = FiniteSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        solver.set_moving_barriers(vec![MovingBarrier {
+            offset_x: TimeSeries::Sinusoid {
+                mean: 2.0,
+                amplitude: 2.0,
+                period: 40.0,
+            },
+            ..MovingBarrier::new(AABBBarrier {
+                bottom_left: Vector2::new(0, 0),
+                top_right: Vector2::new(1, 0),
+                ..Default::default()
+            })
+        }]);
+        let volume = solver.volume();
+        let mut max_velocity = 0.0f32;
+        for _ in 0..200 {
+            solver.time_step(&[]);
+            // faces downstream of the furthest the paddle reaches
+            max_velocity = max_velocity.max(solver.u().get(10, 0).abs());
+            assert!(solver.h().iter().all(|h| h.is_finite() && h >= 0.0));
+        }
+        assert!(max_velocity > 0.01);
+        assert!((solver.volume() - volume).abs() < 1e-3);
+    }
+    #[test]
+    fn periodic() {
+        let dimensions = Vector2::new(20, 10);
+        let mut solver = FiniteSolver::new(
+            Grid::from_fn(|x, _| if x == 0 { 2.0 } else { 1.0 }, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::periodic(),
+        );
+        let volume = solver.volume();
+        for _ in 0..10 {
+            solver.solve(&[]);
+        }
+        assert!(solver.h.get(19, 5) > 1.0);
+        assert!(solver.u.get(0, 5) != 0.0);
+        assert_eq!(solver.u.get(0, 5), solver.u.get(20, 5));
+        assert!((solver.volume() - volume).abs() < 1e-3);
+    }
+    #[test]
+    fn dry_tiles_skipped() {
+        // pool in the corner of a dry slope
+        let dimensions = Vector2::new(64, 64);
+        let build = || {
+            FiniteSolver::new(
+                Grid::from_fn(|x, y| if x < 8 && y < 8 { 2.0 } else { 0.0 }, dimensions),
+                Grid::from_fn(|x, y| (x + y) as f32 * 0.05, dimensions),
+                Vec::new(),
+                SolverBoundaryConditions::default(),
+            )
+        };
+        let mut solver = build();
+        // one tile covering the whole domain is never skipped
+        let mut dense = build();
+        dense.active = ActiveTiles::new(dimensions, 64);
+        for _ in 0..500 {
+            solver.solve(&[]);
+            dense.solve(&[]);
+        }
+        assert!(solver.active.count() < 16);
+        assert!(solver.h.get(8, 4) > 0.0);
+        for x in 0..64 {
+            for y in 0..64 {
+                assert_eq!(solver.h.get(x, y), dense.h.get(x, y));
+            }
+        }
+    }
+    #[test]
+    fn obstacles() {
+        let dimensions = Vector2::new(20, 20);
+        let mut solver = FiniteSolver::new(
+            Grid::from_fn(|x, _| if x < 5 { 2.0 } else { 1.0 }, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        solver.set_obstacles(vec![Obstacle::Mask(Grid::from_fn(
+            |x, y| x == 10 && y < 15,
+            dimensions,
+        ))]);
+        // barrier closes the gap left by the mask
+        let barrier = AABBBarrier {
+            bottom_left: Vector2::new(10, 15),
+            top_right: Vector2::new(10, 19),
+            ..Default::default()
+        };
+        let volume = solver.volume();
+        for _ in 0..50 {
+            solver.solve(&[barrier]);
+        }
+        assert!(solver.obstacle_mask().get(10, 19));
+        for x in 10..dimensions.x {
+            for y in 0..dimensions.y {
+                assert_eq!(solver.h.get(x, y), 1.0);
+            }
+        }
+        assert!(solver.h.get(5, 10) > 1.0);
+        assert!((solver.volume() - volume).abs() < 1e-3);
+    }
+}
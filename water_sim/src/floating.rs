@@ -0,0 +1,237 @@
+use super::Grid;
+use nalgebra::Vector2;
+const G: f32 = 9.81;
+/// fraction of the vertical velocity lost per unit time, stops bodies bobbing forever
+const HEAVE_DAMPING: f32 = 1.0;
+/// fraction of the horizontal velocity lost per unit time by bodies resting on the ground
+const GROUND_FRICTION: f32 = 2.0;
+/// shallowest water the reaction of a body is spread over, keeps thin films from being flung
+const MIN_DEPTH: f32 = 0.01;
+/// Rigid box floating on the water such as a boat or a log. The body is held up by the water
+/// it displaces under its footprint, slides down the slope of the surface and is dragged along
+/// by the flow. With two way coupling the water is dragged back by the body
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FloatingBody {
+    /// centre of the footprint in cells, cell centres are at integer coordinates
+    pub position: Vector2<f32>,
+    /// in cells per unit time
+    pub velocity: Vector2<f32>,
+    /// half of the size of the footprint in cells
+    pub half_size: Vector2<f32>,
+    /// distance from the bottom of the body to its top
+    pub height: f32,
+    /// density relative to water, bodies denser than water sink
+    pub density: f32,
+    /// fraction of the difference between the flow and the body velocity taken up per unit time
+    /// when the body is fully submerged
+    pub drag: f32,
+    /// whether the body pushes water back
+    pub two_way: bool,
+    /// height of the bottom of the body
+    pub elevation: f32,
+    pub vertical_velocity: f32,
+}
+impl FloatingBody {
+    /// builds body at rest with its bottom at height zero, use settle to place it on the water
+    pub fn new(position: Vector2<f32>, half_size: Vector2<f32>, height: f32, density: f32) -> Self {
+        Self {
+            position,
+            velocity: Vector2::new(0.0, 0.0),
+            half_size,
+            height,
+            density,
+            drag: 1.0,
+            two_way: false,
+            elevation: 0.0,
+            vertical_velocity: 0.0,
+        }
+    }
+    /// depth the body floats at in still water
+    pub fn draft(&self) -> f32 {
+        self.density.min(1.0) * self.height
+    }
+    /// cells whose centres are under the footprint, at least the cell under the centre
+    pub fn footprint(&self, dim_x: usize, dim_y: usize) -> Vec<(usize, usize)> {
+        let range = |center: f32, half_size: f32, dim: usize| {
+            let last = (dim - 1) as f32;
+            let start = (center - half_size + 0.5).floor().clamp(0.0, last) as usize;
+            let end = (center + half_size - 0.5).ceil().clamp(0.0, last) as usize;
+            start..=end.max(start)
+        };
+        let mut cells = vec![];
+        for x in range(self.position.x, self.half_size.x, dim_x) {
+            for y in range(self.position.y, self.half_size.y, dim_y) {
+                cells.push((x, y));
+            }
+        }
+        cells
+    }
+    /// places the body at rest floating on the water under it, or resting on the ground if
+    /// the water is too shallow
+    pub fn settle(&mut self, water: &Grid<f32>, ground: &Grid<f32>) {
+        let cells = self.footprint(water.x(), water.y());
+        let surface = cells
+            .iter()
+            .map(|(x, y)| water.get(*x, *y) + ground.get(*x, *y))
+            .sum::<f32>()
+            / cells.len() as f32;
+        self.elevation = (surface - self.draft()).max(Self::floor(&cells, ground));
+        self.velocity = Vector2::new(0.0, 0.0);
+        self.vertical_velocity = 0.0;
+    }
+    /// highest ground under the body
+    fn floor(cells: &[(usize, usize)], ground: &Grid<f32>) -> f32 {
+        cells
+            .iter()
+            .map(|(x, y)| ground.get(*x, *y))
+            .fold(f32::NEG_INFINITY, f32::max)
+    }
+    /// Moves the body by buoyancy, the slope of the surface and drag from velocity, which is
+    /// the flow in cells per unit time at cell centres. cell_size is the length the slope of the
+    /// surface is measured over and is_blocked is true for cells the body can not enter. Returns
+    /// the acceleration of the water in cells per unit time squared at each cell the body
+    /// pushes back on, empty without two way coupling
+    pub(crate) fn step(
+        &mut self,
+        water: &Grid<f32>,
+        ground: &Grid<f32>,
+        velocity: &Grid<Vector2<f32>>,
+        cell_size: f32,
+        delta_t: f32,
+        is_blocked: impl Fn(i32, i32) -> bool,
+    ) -> Vec<((usize, usize), Vector2<f32>)> {
+        let (dim_x, dim_y) = (water.x(), water.y());
+        let cells = self.footprint(dim_x, dim_y);
+        let surface = |x: usize, y: usize| water.get(x, y) + ground.get(x, y);
+        let submerged = cells
+            .iter()
+            .map(|(x, y)| (surface(*x, *y) - self.elevation).clamp(0.0, self.height))
+            .collect::<Vec<_>>();
+        let volume = submerged.iter().sum::<f32>();
+        // fraction of the body under water
+        let displaced = volume / (self.height * cells.len() as f32);
+        let mut flow = Vector2::new(0.0, 0.0);
+        let mut slope = Vector2::new(0.0, 0.0);
+        if volume > 0.0 {
+            for ((x, y), submerged) in cells.iter().zip(submerged.iter()) {
+                let (x, y) = (*x, *y);
+                // one sided differences at the edges of the domain
+                let (x_0, x_1) = (x.saturating_sub(1), (x + 1).min(dim_x - 1));
+                let (y_0, y_1) = (y.saturating_sub(1), (y + 1).min(dim_y - 1));
+                let gradient = Vector2::new(
+                    (surface(x_1, y) - surface(x_0, y)) / (x_1 - x_0).max(1) as f32,
+                    (surface(x, y_1) - surface(x, y_0)) / (y_1 - y_0).max(1) as f32,
+                );
+                flow += velocity.get(x, y) * *submerged / volume;
+                slope += gradient * *submerged / volume;
+            }
+        }
+
+        let density = self.density.max(f32::EPSILON);
+        self.vertical_velocity += delta_t * G * (displaced / density - 1.0);
+        self.vertical_velocity *= (1.0 - HEAVE_DAMPING * delta_t).max(0.0);
+        self.elevation += self.vertical_velocity * delta_t;
+        let floor = Self::floor(&cells, ground);
+        let grounded = self.elevation <= floor;
+        if grounded {
+            self.elevation = floor;
+            self.vertical_velocity = self.vertical_velocity.max(0.0);
+        }
+
+        // taken up at most fully so that drag does not overshoot the flow
+        let drag = (self.drag * displaced * delta_t).min(1.0);
+        let drag_change = (flow - self.velocity) * drag;
+        let buoyancy = (displaced / density).min(1.0);
+        self.velocity += drag_change - delta_t * G * buoyancy * slope / cell_size;
+        if grounded {
+            self.velocity *= (1.0 - GROUND_FRICTION * delta_t).max(0.0);
+        }
+        let old_position = self.position;
+        self.position += self.velocity * delta_t;
+        // kept inside of the domain
+        for (i, dim) in [dim_x, dim_y].into_iter().enumerate() {
+            let low = self.half_size[i] - 0.5;
+            let high = (dim as f32 - 0.5 - self.half_size[i]).max(low);
+            if self.position[i] < low || self.position[i] > high {
+                self.position[i] = self.position[i].clamp(low, high);
+                self.velocity[i] = 0.0;
+            }
+        }
+        if self
+            .footprint(dim_x, dim_y)
+            .iter()
+            .any(|(x, y)| is_blocked(*x as i32, *y as i32))
+        {
+            self.position = old_position;
+            self.velocity = Vector2::new(0.0, 0.0);
+        }
+
+        if !self.two_way || volume <= 0.0 {
+            return vec![];
+        }
+        // the body's momentum change from drag is taken from the water it displaces
+        let mass = self.density * self.height * cells.len() as f32;
+        let force = mass * drag_change / delta_t;
+        cells
+            .iter()
+            .zip(submerged.iter())
+            .filter(|(_, submerged)| **submerged > 0.0)
+            .map(|((x, y), submerged)| {
+                let depth = water.get(*x, *y).max(MIN_DEPTH);
+                ((*x, *y), -force * (*submerged / volume) / depth)
+            })
+            .collect()
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn floats_at_draft() {
+        let dimensions = Vector2::new(10, 10);
+        let water = Grid::from_fn(|_, _| 2.0, dimensions);
+        let ground = Grid::from_fn(|_, _| 0.0, dimensions);
+        let velocity = Grid::from_fn(|_, _| Vector2::new(0.0, 0.0), dimensions);
+        // dropped from above the water
+        let mut body = FloatingBody::new(Vector2::new(5.0, 5.0), Vector2::new(1.0, 1.0), 1.0, 0.5);
+        body.elevation = 2.5;
+        for _ in 0..500 {
+            body.step(&water, &ground, &velocity, 1.0, 0.1, |_, _| false);
+        }
+        assert!((body.elevation - (2.0 - body.draft())).abs() < 1e-2);
+        assert_eq!(body.position, Vector2::new(5.0, 5.0));
+        // bodies denser than water sink to the bottom
+        let mut stone = FloatingBody::new(Vector2::new(5.0, 5.0), Vector2::new(1.0, 1.0), 1.0, 2.0);
+        stone.settle(&water, &ground);
+        for _ in 0..500 {
+            stone.step(&water, &ground, &velocity, 1.0, 0.1, |_, _| false);
+        }
+        assert_eq!(stone.elevation, 0.0);
+    }
+    #[test]
+    fn dragged_by_flow() {
+        let dimensions = Vector2::new(30, 10);
+        let water = Grid::from_fn(|_, _| 2.0, dimensions);
+        let ground = Grid::from_fn(|_, _| 0.0, dimensions);
+        let velocity = Grid::from_fn(|_, _| Vector2::new(1.0, 0.0), dimensions);
+        let mut body = FloatingBody::new(Vector2::new(5.0, 5.0), Vector2::new(1.0, 0.5), 1.0, 0.5);
+        body.two_way = true;
+        body.settle(&water, &ground);
+        let reaction = body.step(&water, &ground, &velocity, 1.0, 0.1, |_, _| false);
+        // the water is held back where the body is dragged forwards
+        assert!(!reaction.is_empty());
+        assert!(reaction
+            .iter()
+            .all(|(_, acceleration)| acceleration.x < 0.0));
+        for _ in 0..100 {
+            body.step(&water, &ground, &velocity, 1.0, 0.1, |_, _| false);
+        }
+        assert!((body.velocity.x - 1.0).abs() < 1e-2);
+        // stopped by a barrier
+        for _ in 0..200 {
+            body.step(&water, &ground, &velocity, 1.0, 0.1, |x, _| x == 25);
+        }
+        assert!(body.position.x < 24.0);
+        assert_eq!(body.velocity.x, 0.0);
+    }
+}
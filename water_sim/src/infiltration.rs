@@ -0,0 +1,77 @@
+use super::{Grid, MassBalance};
+use nalgebra::Vector2;
+/// Capacity based soil absorption. Dry soil absorbs water at its full rate, the rate drops
+/// linearly to zero as the soil fills up to its capacity
+#[derive(Clone)]
+pub struct Infiltration {
+    /// max depth of water the soil in each cell can hold
+    pub capacity: Grid<f32>,
+    /// depth of water absorbed per timestep by dry soil
+    pub rate: Grid<f32>,
+    /// depth of water currently held by the soil
+    moisture: Grid<f32>,
+}
+impl Infiltration {
+    /// builds infiltration with dry soil
+    pub fn new(capacity: Grid<f32>, rate: Grid<f32>) -> Self {
+        assert_eq!(capacity.x(), rate.x());
+        assert_eq!(capacity.y(), rate.y());
+        let dimensions = Vector2::new(capacity.x(), capacity.y());
+        Self {
+            capacity,
+            rate,
+            moisture: Grid::from_fn(|_, _| 0.0, dimensions),
+        }
+    }
+    /// builds infiltration with the same soil everywhere
+    pub fn uniform(dimensions: Vector2<usize>, capacity: f32, rate: f32) -> Self {
+        Self::new(
+            Grid::from_fn(|_, _| capacity, dimensions),
+            Grid::from_fn(|_, _| rate, dimensions),
+        )
+    }
+    /// depth of water held by soil
+    pub fn moisture(&self) -> &Grid<f32> {
+        &self.moisture
+    }
+    /// fraction of capacity filled at cell, between 0 and 1
+    pub fn saturation(&self, x: usize, y: usize) -> f32 {
+        let capacity = self.capacity.get(x, y);
+        if capacity > 0.0 {
+            (self.moisture.get(x, y) / capacity).min(1.0)
+        } else {
+            1.0
+        }
+    }
+    /// moves water into the soil, absorbed volume is recorded in balance
+    pub fn apply(&mut self, water: &mut Grid<f32>, balance: &mut MassBalance) {
+        for x in 0..water.x() {
+            for y in 0..water.y() {
+                let capacity = self.capacity.get(x, y);
+                let moisture = self.moisture.get(x, y);
+                let rate = self.rate.get(x, y) * (1.0 - self.saturation(x, y));
+                let absorbed = rate.min(water.get(x, y)).min(capacity - moisture).max(0.0);
+                *water.get_mut(x, y) -= absorbed;
+                *self.moisture.get_mut(x, y) += absorbed;
+                balance.infiltration += absorbed;
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn soil_saturates() {
+        let dimensions = Vector2::new(5, 5);
+        let mut water = Grid::from_fn(|_, _| 1.0, dimensions);
+        let mut infiltration = Infiltration::uniform(dimensions, 0.1, 0.01);
+        let mut balance = MassBalance::default();
+        for _ in 0..200 {
+            infiltration.apply(&mut water, &mut balance);
+        }
+        assert!(infiltration.saturation(2, 2) > 0.99);
+        assert!((water.get(2, 2) + infiltration.moisture().get(2, 2) - 1.0).abs() < 1e-5);
+        assert!((balance.infiltration - 25.0 * infiltration.moisture().get(2, 2)).abs() < 1e-3);
+    }
+}
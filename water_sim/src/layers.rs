@@ -0,0 +1,155 @@
+use super::Grid;
+use nalgebra::Vector2;
+/// layers thinner than this are treated as worn through, so rounding errors left over from
+/// syncing with the ground do not show up as exposed material
+const MIN_THICKNESS: f32 = 1e-4;
+/// Kind of ground a layer is made of
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    /// multiplier of the erosion rate, loose material erodes faster
+    pub erodibility: f32,
+    /// rgb colour of the ground where the material is exposed
+    pub color: [f32; 3],
+}
+impl Material {
+    pub const SAND: Self = Self {
+        erodibility: 1.0,
+        color: [0.9, 0.8, 0.55],
+    };
+    pub const CLAY: Self = Self {
+        erodibility: 0.4,
+        color: [0.65, 0.45, 0.3],
+    };
+    pub const ROCK: Self = Self {
+        erodibility: 0.05,
+        color: [0.5, 0.5, 0.5],
+    };
+}
+/// layer of ground with its thickness in each cell
+#[derive(Clone)]
+pub struct Layer {
+    pub material: Material,
+    pub thickness: Grid<f32>,
+}
+/// Ground made of layers stacked on bedrock. The first layer lies on the bedrock and the last
+/// is the top of the stack. Material removed from a cell comes off the highest layer with
+/// thickness left, material added to a cell is laid down on the top layer
+#[derive(Clone)]
+pub struct Layers {
+    /// material under all of the layers
+    pub bedrock: Material,
+    /// height of the bedrock surface
+    base: Grid<f32>,
+    layers: Vec<Layer>,
+}
+impl Layers {
+    pub fn new(bedrock: Material, base: Grid<f32>, layers: Vec<Layer>) -> Self {
+        for layer in layers.iter() {
+            assert_eq!(layer.thickness.x(), base.x());
+            assert_eq!(layer.thickness.y(), base.y());
+        }
+        Self {
+            bedrock,
+            base,
+            layers,
+        }
+    }
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+    /// height of the ground surface at cell
+    pub fn height(&self, x: usize, y: usize) -> f32 {
+        self.base.get(x, y)
+            + self
+                .layers
+                .iter()
+                .map(|layer| layer.thickness.get(x, y))
+                .sum::<f32>()
+    }
+    /// height of the ground surface, used as the ground of a solver
+    pub fn surface(&self) -> Grid<f32> {
+        let dimensions = Vector2::new(self.base.x(), self.base.y());
+        Grid::from_fn(|x, y| self.height(x, y), dimensions)
+    }
+    /// index of the highest layer with thickness left at cell, None if the bedrock is exposed
+    pub fn exposed(&self, x: usize, y: usize) -> Option<usize> {
+        self.layers
+            .iter()
+            .rposition(|layer| layer.thickness.get(x, y) > MIN_THICKNESS)
+    }
+    /// material at the surface of cell
+    pub fn exposed_material(&self, x: usize, y: usize) -> &Material {
+        match self.exposed(x, y) {
+            Some(i) => &self.layers[i].material,
+            None => &self.bedrock,
+        }
+    }
+    /// erodibility of the material at the surface of cell
+    pub fn erodibility(&self, x: usize, y: usize) -> f32 {
+        self.exposed_material(x, y).erodibility
+    }
+    /// Updates the layers to match ground after it was eroded, deposited or edited. Lowered
+    /// cells lose material from the top down, once every layer is gone the bedrock is lowered.
+    /// Raised cells gain material on the top layer
+    pub(crate) fn sync(&mut self, ground: &Grid<f32>) {
+        for x in 0..ground.x() {
+            for y in 0..ground.y() {
+                let change = ground.get(x, y) - self.height(x, y);
+                if change > 0.0 {
+                    match self.layers.last_mut() {
+                        Some(top) => *top.thickness.get_mut(x, y) += change,
+                        None => *self.base.get_mut(x, y) += change,
+                    }
+                } else if change < 0.0 {
+                    let mut removed = -change;
+                    for layer in self.layers.iter_mut().rev() {
+                        let thickness = layer.thickness.get_mut(x, y);
+                        let taken = removed.min(*thickness);
+                        *thickness -= taken;
+                        removed -= taken;
+                    }
+                    *self.base.get_mut(x, y) -= removed;
+                }
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn erosion_exposes_layer_beneath() {
+        let dimensions = Vector2::new(2, 1);
+        let mut layers = Layers::new(
+            Material::ROCK,
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            vec![
+                Layer {
+                    material: Material::CLAY,
+                    thickness: Grid::from_fn(|_, _| 1.0, dimensions),
+                },
+                Layer {
+                    material: Material::SAND,
+                    thickness: Grid::from_fn(|_, _| 0.5, dimensions),
+                },
+            ],
+        );
+        assert_eq!(layers.exposed_material(0, 0), &Material::SAND);
+        let mut ground = layers.surface();
+        *ground.get_mut(0, 0) = 0.75;
+        layers.sync(&ground);
+        assert_eq!(layers.exposed_material(0, 0), &Material::CLAY);
+        assert_eq!(layers.exposed_material(1, 0), &Material::SAND);
+        // cut through to the bedrock
+        *ground.get_mut(0, 0) = -0.5;
+        layers.sync(&ground);
+        assert_eq!(layers.exposed(0, 0), None);
+        assert_eq!(layers.erodibility(0, 0), Material::ROCK.erodibility);
+        assert_eq!(layers.height(0, 0), -0.5);
+        // deposits are laid on the top layer
+        *ground.get_mut(0, 0) = 0.0;
+        layers.sync(&ground);
+        assert_eq!(layers.exposed_material(0, 0), &Material::SAND);
+        assert_eq!(layers.layers()[1].thickness.get(0, 0), 0.5);
+    }
+}
@@ -1,16 +1,45 @@
 pub mod aabb;
+mod barrier;
 
+mod coriolis;
+mod erosion;
 mod finite_solver;
+mod floating;
+mod infiltration;
+mod layers;
+mod moving_barrier;
+mod nested;
+mod particles;
 mod pipe_solver;
+mod precipitation;
 mod source;
+mod sponge;
+mod temperature;
+mod time_series;
+mod tracer;
+mod wind;
 
 pub use aabb::AABBBarrier;
+pub use barrier::Obstacle;
 use bevy::prelude::*;
-pub use grid::{Grid, Vector};
-use std::{fs::File, io::Write};
+pub use coriolis::Coriolis;
+pub use erosion::{Erosion, ThermalErosion};
+pub use grid::{Grid, Interpolation, Vector};
+use std::{fs::File, io::Write, sync::Arc};
 
 pub use finite_solver::FiniteSolver;
-pub use source::Source;
+pub use floating::FloatingBody;
+pub use infiltration::Infiltration;
+pub use layers::{Layer, Layers, Material};
+pub use moving_barrier::MovingBarrier;
+pub use nested::NestedGrid;
+pub use particles::{Integrator, Particle, Particles};
+pub use precipitation::Precipitation;
+pub use source::{Source, SourceForm};
+pub use temperature::Temperature;
+pub use time_series::TimeSeries;
+pub use tracer::Tracer;
+pub use wind::{Wind, WindEnvelope, WindField};
 
 /// size in x direction of water surface
 /// Does not depend on mesh resolution
@@ -23,18 +52,283 @@ pub struct SolveInfo {
     pub name: &'static str,
     pub data: String,
 }
-#[derive(Clone, Copy, Debug)]
+/// Running totals of water added and removed by source terms. Volumes are water heights
+/// summed over cells
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MassBalance {
+    pub rain: f32,
+    pub evaporation: f32,
+    pub infiltration: f32,
+    /// net volume added by sources in flux form
+    pub sources: f32,
+}
+impl MassBalance {
+    /// formats balance for display, volume is current volume of water
+    pub fn solve_info(&self, volume: f32) -> Vec<SolveInfo> {
+        vec![
+            SolveInfo {
+                name: "Volume",
+                data: format!("{:.2}", volume),
+            },
+            SolveInfo {
+                name: "Rained",
+                data: format!("{:.2}", self.rain),
+            },
+            SolveInfo {
+                name: "Evaporated",
+                data: format!("{:.2}", self.evaporation),
+            },
+            SolveInfo {
+                name: "Infiltrated",
+                data: format!("{:.2}", self.infiltration),
+            },
+            SolveInfo {
+                name: "Sources",
+                data: format!("{:.2}", self.sources),
+            },
+        ]
+    }
+}
+#[derive(Clone, Debug)]
 pub enum BoundaryConditions {
     Reflect,
     Absorb,
-    Ocean { level: f32 },
+    /// water level outside of the boundary, water flows in and out to match it
+    Ocean {
+        level: f32,
+    },
+    /// ocean whose level changes over time, for example with the tide
+    Tide {
+        level: TimeSeries,
+    },
+    /// Discharge in volume per unit time spread evenly over the cells of the edge or segment it
+    /// is set on, the boundary is a wall for outgoing water
+    Inflow {
+        discharge: TimeSeries,
+    },
+    /// water leaves at critical depth as if falling off of a ledge
+    FreeOutflow,
+    /// Absorbing layer width cells wide that damps waves by relaxing the water towards the still
+    /// water level, outside of the layer behaves like an ocean at level
+    Sponge {
+        width: usize,
+        level: f32,
+    },
+    /// Wraps around to the opposite edge, which must also be periodic at the same cells
+    Periodic,
+}
+impl BoundaryConditions {
+    /// whether water can leave through boundary
+    pub fn is_open(&self) -> bool {
+        !matches!(self, Self::Reflect | Self::Inflow { .. })
+    }
+}
+/// Edge of the simulation domain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edge {
+    XPlus,
+    XMinus,
+    YPlus,
+    YMinus,
+}
+impl Edge {
+    pub const ALL: [Edge; 4] = [Edge::XPlus, Edge::XMinus, Edge::YPlus, Edge::YMinus];
+    /// number of cells along edge
+    pub fn len(&self, dim_x: usize, dim_y: usize) -> usize {
+        match self {
+            Self::XPlus | Self::XMinus => dim_y,
+            Self::YPlus | Self::YMinus => dim_x,
+        }
+    }
+    /// cell i along edge
+    pub fn cell(&self, dim_x: usize, dim_y: usize, i: usize) -> (usize, usize) {
+        match self {
+            Self::XPlus => (dim_x - 1, i),
+            Self::XMinus => (0, i),
+            Self::YPlus => (i, dim_y - 1),
+            Self::YMinus => (i, 0),
+        }
+    }
+    /// position of cell (x, y) along edge
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        match self {
+            Self::XPlus | Self::XMinus => y,
+            Self::YPlus | Self::YMinus => x,
+        }
+    }
+    /// Neighbour of cell (x, y) in the direction of edge, None if the neighbour is outside
+    /// of the domain
+    pub fn neighbour(
+        &self,
+        dim_x: usize,
+        dim_y: usize,
+        x: usize,
+        y: usize,
+    ) -> Option<(usize, usize)> {
+        match self {
+            Self::XPlus => (x + 1 < dim_x).then(|| (x + 1, y)),
+            Self::XMinus => x.checked_sub(1).map(|x| (x, y)),
+            Self::YPlus => (y + 1 < dim_y).then(|| (x, y + 1)),
+            Self::YMinus => y.checked_sub(1).map(|y| (x, y)),
+        }
+    }
+    pub fn opposite(&self) -> Self {
+        match self {
+            Self::XPlus => Self::XMinus,
+            Self::XMinus => Self::XPlus,
+            Self::YPlus => Self::YMinus,
+            Self::YMinus => Self::YPlus,
+        }
+    }
 }
+/// Boundary conditions that can not be simulated
 #[derive(Clone, Copy, Debug)]
+pub enum BoundaryError {
+    /// cell index along edge is periodic but the same cell on the opposite edge is not
+    UnmatchedPeriodic { edge: Edge, index: usize },
+    /// cell index along edge is covered by a nested grid and its condition can not be refined
+    NotRefinable { edge: Edge, index: usize },
+    /// nested grid with its corner at origin and size in coarse cells goes past the domain
+    NestedOutsideDomain {
+        origin: Vector2<usize>,
+        size: Vector2<usize>,
+    },
+    /// nested grid with a ratio of zero has no fine cells
+    NestedRatioZero,
+}
+impl std::fmt::Display for BoundaryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnmatchedPeriodic { edge, index } => write!(
+                f,
+                "cell {} of edge {:?} is periodic but cell {} of edge {:?} is not",
+                index,
+                edge,
+                index,
+                edge.opposite()
+            ),
+            Self::NotRefinable { edge, index } => write!(
+                f,
+                "cell {} of edge {:?} is covered by a nested grid but its condition can not be refined",
+                index, edge
+            ),
+            Self::NestedOutsideDomain { origin, size } => write!(
+                f,
+                "nested grid at ({}, {}) of size ({}, {}) goes past the edge of the domain",
+                origin.x, origin.y, size.x, size.y
+            ),
+            Self::NestedRatioZero => write!(f, "nested grid needs a ratio of at least one"),
+        }
+    }
+}
+/// Overrides the condition of cells start..end along one edge
+#[derive(Clone, Debug)]
+pub struct BoundarySegment {
+    pub edge: Edge,
+    pub start: usize,
+    pub end: usize,
+    pub condition: BoundaryConditions,
+}
+#[derive(Clone, Debug)]
 pub struct SolverBoundaryConditions {
     pub x_plus: BoundaryConditions,
     pub x_minus: BoundaryConditions,
     pub y_plus: BoundaryConditions,
     pub y_minus: BoundaryConditions,
+    /// parts of edges with a different condition than the rest of the edge, when segments
+    /// overlap the later one is used
+    pub segments: Vec<BoundarySegment>,
+}
+impl SolverBoundaryConditions {
+    /// domain that wraps around in both directions
+    pub fn periodic() -> Self {
+        Self {
+            x_plus: BoundaryConditions::Periodic,
+            x_minus: BoundaryConditions::Periodic,
+            y_plus: BoundaryConditions::Periodic,
+            y_minus: BoundaryConditions::Periodic,
+            segments: Vec::new(),
+        }
+    }
+    /// condition used on edge where it is not overridden by a segment
+    pub fn get(&self, edge: Edge) -> &BoundaryConditions {
+        match edge {
+            Edge::XPlus => &self.x_plus,
+            Edge::XMinus => &self.x_minus,
+            Edge::YPlus => &self.y_plus,
+            Edge::YMinus => &self.y_minus,
+        }
+    }
+    /// index of segment that sets the condition of cell i along edge
+    fn segment(&self, edge: Edge, i: usize) -> Option<usize> {
+        self.segments
+            .iter()
+            .rposition(|segment| segment.edge == edge && (segment.start..segment.end).contains(&i))
+    }
+    /// condition of cell i along edge
+    pub fn at(&self, edge: Edge, i: usize) -> &BoundaryConditions {
+        self.segment(edge, i)
+            .map(|idx| &self.segments[idx].condition)
+            .unwrap_or_else(|| self.get(edge))
+    }
+    /// whether any cell of edge wraps around to the opposite edge
+    pub fn wraps(&self, edge: Edge, dim_x: usize, dim_y: usize) -> bool {
+        (0..edge.len(dim_x, dim_y))
+            .any(|i| matches!(self.at(edge, i), BoundaryConditions::Periodic))
+    }
+    /// Neighbour of cell (x, y) in the direction of edge. Neighbours across periodic boundaries
+    /// wrap around to the opposite edge, None if the neighbour is outside of the domain
+    pub fn neighbour(
+        &self,
+        edge: Edge,
+        dim_x: usize,
+        dim_y: usize,
+        x: usize,
+        y: usize,
+    ) -> Option<(usize, usize)> {
+        edge.neighbour(dim_x, dim_y, x, y).or_else(|| {
+            let index = edge.index(x, y);
+            match self.at(edge, index) {
+                BoundaryConditions::Periodic => Some(edge.opposite().cell(dim_x, dim_y, index)),
+                _ => None,
+            }
+        })
+    }
+    /// checks that every periodic cell is matched by a periodic cell on the opposite edge
+    pub fn validate(&self, dim_x: usize, dim_y: usize) -> Result<(), BoundaryError> {
+        for edge in Edge::ALL {
+            for index in 0..edge.len(dim_x, dim_y) {
+                let periodic = matches!(self.at(edge, index), BoundaryConditions::Periodic);
+                let opposite_periodic = matches!(
+                    self.at(edge.opposite(), index),
+                    BoundaryConditions::Periodic
+                );
+                if periodic && !opposite_periodic {
+                    return Err(BoundaryError::UnmatchedPeriodic { edge, index });
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Adds water flowing in through inflow boundaries at timestep t. Each cell has an area of
+    /// one so volume is added directly to water height
+    pub(crate) fn apply_inflow(&self, water: &mut Grid<f32>, t: u32, delta_t: f32) {
+        for edge in Edge::ALL {
+            let len = edge.len(water.x(), water.y());
+            // number of cells each condition is used on, the edge default is stored last
+            let mut counts = vec![0usize; self.segments.len() + 1];
+            for i in 0..len {
+                counts[self.segment(edge, i).unwrap_or(self.segments.len())] += 1;
+            }
+            for i in 0..len {
+                let owner = self.segment(edge, i).unwrap_or(self.segments.len());
+                if let BoundaryConditions::Inflow { discharge } = self.at(edge, i) {
+                    let (x, y) = edge.cell(water.x(), water.y(), i);
+                    *water.get_mut(x, y) += discharge.value(t) * delta_t / counts[owner] as f32;
+                }
+            }
+        }
+    }
 }
 impl Default for SolverBoundaryConditions {
     fn default() -> Self {
@@ -43,6 +337,7 @@ impl Default for SolverBoundaryConditions {
             x_minus: BoundaryConditions::Reflect,
             y_plus: BoundaryConditions::Reflect,
             y_minus: BoundaryConditions::Reflect,
+            segments: Vec::new(),
         }
     }
 }
@@ -61,21 +356,89 @@ pub trait Solver {
     fn dim_x(&self) -> usize;
     fn dim_y(&self) -> usize;
     fn get_ground_mut(&mut self, x: usize, y: usize) -> &mut f32;
+    /// sets rotation of the domain, off by default
+    fn set_coriolis(&mut self, coriolis: Coriolis);
+    /// sets wind blowing over the surface, no wind by default
+    fn set_wind(&mut self, wind: Option<Wind>);
+    /// sets rain and evaporation, none by default
+    fn set_precipitation(&mut self, precipitation: Option<Precipitation>);
+    /// sets soil absorbing water, none by default
+    fn set_infiltration(&mut self, infiltration: Option<Infiltration>);
+    /// soil state, used for rendering soil moisture
+    fn infiltration(&self) -> Option<&Infiltration>;
+    /// sets erosion and deposition of ground by the flow, off by default
+    fn set_erosion(&mut self, erosion: Option<Erosion>);
+    /// sediment state, None if erosion is off
+    fn erosion(&self) -> Option<&Erosion>;
+    /// sets collapse of slopes steeper than the angle of repose, off by default
+    fn set_thermal_erosion(&mut self, thermal_erosion: Option<ThermalErosion>);
+    /// Sets ground made of stacked layers, the surface of the layers should match the ground the
+    /// solver was built with. None by default, where all ground erodes alike
+    fn set_layers(&mut self, layers: Option<Layers>);
+    /// layers of ground, used for rendering the exposed material
+    fn layers(&self) -> Option<&Layers>;
+    /// sets passive tracers carried by the water, none by default
+    fn set_tracers(&mut self, tracers: Vec<Tracer>);
+    fn tracers(&self) -> &[Tracer];
+    /// used for injecting tracer
+    fn tracers_mut(&mut self) -> &mut [Tracer];
+    /// sets temperature of the water driving density currents, off by default where all water
+    /// has the same density
+    fn set_temperature(&mut self, temperature: Option<Temperature>);
+    fn temperature(&self) -> Option<&Temperature>;
+    /// sets particles carried by the flow, none by default
+    fn set_particles(&mut self, particles: Option<Particles>);
+    fn particles(&self) -> Option<&Particles>;
+    /// used for seeding particles
+    fn particles_mut(&mut self) -> Option<&mut Particles>;
+    /// sets bodies floating on the water, none by default
+    fn set_bodies(&mut self, bodies: Vec<FloatingBody>);
+    fn bodies(&self) -> &[FloatingBody];
+    /// used for adding bodies and moving them by hand
+    fn bodies_mut(&mut self) -> &mut Vec<FloatingBody>;
+    /// velocity of the water at each cell centre in cells per unit time
+    fn velocity_field(&self) -> Grid<Vector2<f32>>;
+    /// water added and removed by source terms so far
+    fn mass_balance(&self) -> MassBalance;
+    /// sets how sources change the water, height form by default
+    fn set_source_form(&mut self, form: SourceForm);
+    /// sets obstacles water can not flow through in addition to the barriers passed to solve,
+    /// none by default
+    fn set_obstacles(&mut self, obstacles: Vec<Obstacle>);
+    /// cells blocked by obstacles and barriers
+    fn obstacle_mask(&self) -> &Grid<bool>;
+    /// sets barriers moved by scripts in addition to the barriers passed to solve, none by
+    /// default
+    fn set_moving_barriers(&mut self, barriers: Vec<MovingBarrier>);
+    /// moving barriers where they are at the current step, used for rendering
+    fn moving_barriers(&self) -> Vec<AABBBarrier>;
+    /// Simulates a region of the domain on a finer grid built from the current water and
+    /// ground, None by default. Fails if the region does not fit in the domain or covers
+    /// boundary conditions that can not be refined, leaving the solver without a nested grid
+    fn set_nested_grid(&mut self, nest: Option<NestedGrid>) -> Result<(), BoundaryError>;
+    /// refined region with the water on its fine grid
+    fn nested_grid(&self) -> Option<(&NestedGrid, &Grid<f32>)>;
     fn offset_water(&self) -> Grid<f32> {
         self.water_h().clone() + self.ground_h().clone()
     }
     fn numpy_data(&self) -> Vec<u8> {
         self.water_h().numpy_data()
     }
+    /// Water, ground and the mass of each tracer saved as layers of a numpy archive, used for
+    /// checkpoints
+    fn checkpoint_data(&self) -> Vec<u8> {
+        let mut layers = vec![self.water_h(), self.ground_h()];
+        layers.extend(self.tracers().iter().map(|tracer| tracer.mass()));
+        let mut cursor = std::io::Cursor::new(Vec::new());
+        Grid::save_several_layers_writer(&mut cursor, &layers).expect("failed to save checkpoint");
+        cursor.into_inner()
+    }
+    /// total water height summed over every cell
+    fn volume(&self) -> f32 {
+        self.water_h().sum()
+    }
     fn mean_height(&self) -> f32 {
-        let mut sum = 0.0;
-        let water = self.water_h();
-        for x in 0..water.x() {
-            for y in 0..water.y() {
-                sum += water.get(x, y) / (water.x() * water.y()) as f32
-            }
-        }
-        return sum;
+        self.water_h().mean().unwrap_or(0.0)
     }
 }
 pub struct WaterMarker;
@@ -107,14 +470,17 @@ pub fn get_conditions<T: Solver>() -> Vec<InitialConditions<T>> {
                         AABBBarrier {
                             top_right: Vector2::new(30, 110),
                             bottom_left: Vector2::new(-10, 109),
+                            ..Default::default()
                         },
                         AABBBarrier {
                             top_right: Vector2::new(65, 110),
                             bottom_left: Vector2::new(35, 109),
+                            ..Default::default()
                         },
                         AABBBarrier {
                             top_right: Vector2::new(110, 110),
                             bottom_left: Vector2::new(70, 109),
+                            ..Default::default()
                         },
                     ],
                 )
@@ -142,14 +508,64 @@ pub fn get_conditions<T: Solver>() -> Vec<InitialConditions<T>> {
                         AABBBarrier {
                             top_right: Vector2::new(30, 110),
                             bottom_left: Vector2::new(-10, 109),
+                            ..Default::default()
                         },
                         AABBBarrier {
                             top_right: Vector2::new(65, 110),
                             bottom_left: Vector2::new(35, 109),
+                            ..Default::default()
                         },
                         AABBBarrier {
                             top_right: Vector2::new(110, 110),
                             bottom_left: Vector2::new(70, 109),
+                            ..Default::default()
+                        },
+                    ],
+                )
+            },
+        },
+        InitialConditions {
+            name: "Double Slit Nested",
+            build_water_fn: || {
+                // Double Slit Large at half of the resolution away from the slits
+                let dimensions = Vector2::new(50, 500);
+                let h = Grid::from_fn(
+                    |x, y| {
+                        let r = ((x as f32 - 25.0).powi(2) + (y as f32 - 25.0).powi(2)).sqrt();
+                        if r <= 5.0 {
+                            2.0 * (5.0 - r) / 5.0 + 1.0
+                        } else {
+                            1.0
+                        }
+                    },
+                    dimensions,
+                );
+                let g_h = Grid::from_fn(|_x, _y| 0.0, dimensions);
+                let mut solver = T::new(h, g_h, Vec::new(), SolverBoundaryConditions::default());
+                solver
+                    .set_nested_grid(Some(NestedGrid::new(
+                        Vector2::new(0, 45),
+                        Vector2::new(50, 20),
+                        2,
+                    )))
+                    .expect("invalid nested grid");
+                (
+                    solver,
+                    vec![
+                        AABBBarrier {
+                            top_right: Vector2::new(15, 55),
+                            bottom_left: Vector2::new(-5, 54),
+                            ..Default::default()
+                        },
+                        AABBBarrier {
+                            top_right: Vector2::new(32, 55),
+                            bottom_left: Vector2::new(18, 54),
+                            ..Default::default()
+                        },
+                        AABBBarrier {
+                            top_right: Vector2::new(55, 55),
+                            bottom_left: Vector2::new(35, 54),
+                            ..Default::default()
                         },
                     ],
                 )
@@ -263,6 +679,231 @@ pub fn get_conditions<T: Solver>() -> Vec<InitialConditions<T>> {
                 )
             },
         },
+        InitialConditions {
+            name: "Storm Surge",
+            build_water_fn: || {
+                let dimensions = Vector2::new(300, 100);
+                let water_level = 2.0;
+                let ground_fn = |x: usize, _y: usize| {
+                    if x < 200 {
+                        0.0
+                    } else {
+                        (x as f32 - 200.0) / 25.0
+                    }
+                };
+                let g_h = Grid::from_fn(ground_fn, dimensions);
+                let h = Grid::from_fn(|x, y| (water_level - ground_fn(x, y)).max(0.0), dimensions);
+                let mut solver = T::new(h, g_h, Vec::new(), SolverBoundaryConditions::default());
+                // drag is exaggerated so that the surge is visible on a small domain
+                solver.set_wind(Some(Wind {
+                    field: WindField::Uniform(Vector2::new(30.0, 0.0)),
+                    envelope: WindEnvelope::Storm {
+                        start: 0,
+                        peak_start: 1000,
+                        peak_end: 2000,
+                        end: 3000,
+                    },
+                    drag: 5e-5,
+                }));
+
+                (solver, vec![])
+            },
+        },
+        InitialConditions {
+            name: "River",
+            build_water_fn: || {
+                let dimensions = Vector2::new(300, 60);
+                let ground_fn = |x: usize, y: usize| {
+                    let bed = 2.0 - x as f32 / 150.0;
+                    if (y as f32 - 30.0).abs() > 15.0 {
+                        bed + 2.0
+                    } else {
+                        bed
+                    }
+                };
+                let g_h = Grid::from_fn(ground_fn, dimensions);
+                let h = Grid::from_fn(
+                    |_x, y| {
+                        if (y as f32 - 30.0).abs() > 15.0 {
+                            0.0
+                        } else {
+                            0.2
+                        }
+                    },
+                    dimensions,
+                );
+                // flood wave passing down the river
+                let hydrograph =
+                    TimeSeries::Table(Arc::new([(0.0, 5.0), (500.0, 40.0), (1500.0, 5.0)]));
+
+                let mut solver = T::new(
+                    h,
+                    g_h,
+                    Vec::new(),
+                    SolverBoundaryConditions {
+                        x_plus: BoundaryConditions::FreeOutflow,
+                        segments: vec![BoundarySegment {
+                            edge: Edge::XMinus,
+                            start: 15,
+                            end: 46,
+                            condition: BoundaryConditions::Inflow {
+                                discharge: hydrograph,
+                            },
+                        }],
+                        ..Default::default()
+                    },
+                );
+                // sandy bed between harder banks
+                let hardness = Grid::from_fn(
+                    |_x, y| {
+                        if (y as f32 - 30.0).abs() > 15.0 {
+                            0.9
+                        } else {
+                            0.0
+                        }
+                    },
+                    dimensions,
+                );
+                solver.set_erosion(Some(Erosion::new(0.05, 0.2, 0.5, hardness)));
+                // undercut banks slump into the channel
+                solver.set_thermal_erosion(Some(ThermalErosion {
+                    angle_of_repose: 1.0,
+                    rate: 0.5,
+                }));
+                // sand over clay over rock, scouring exposes the harder layers beneath
+                let sand = 0.3;
+                let clay = 0.5;
+                solver.set_layers(Some(Layers::new(
+                    Material::ROCK,
+                    Grid::from_fn(|x, y| ground_fn(x, y) - sand - clay, dimensions),
+                    vec![
+                        Layer {
+                            material: Material::CLAY,
+                            thickness: Grid::from_fn(|_, _| clay, dimensions),
+                        },
+                        Layer {
+                            material: Material::SAND,
+                            thickness: Grid::from_fn(|_, _| sand, dimensions),
+                        },
+                    ],
+                )));
+                (solver, vec![])
+            },
+        },
+        InitialConditions {
+            name: "Canal",
+            build_water_fn: || {
+                let dimensions = Vector2::new(300, 40);
+                let in_canal = |y: usize| (y as f32 - 20.0).abs() <= 8.0;
+                let g_h = Grid::from_fn(|_, y| if in_canal(y) { 0.0 } else { 2.0 }, dimensions);
+                let h = Grid::from_fn(|_, y| if in_canal(y) { 1.0 } else { 0.0 }, dimensions);
+                let mut solver = T::new(
+                    h,
+                    g_h,
+                    Vec::new(),
+                    SolverBoundaryConditions {
+                        x_plus: BoundaryConditions::FreeOutflow,
+                        segments: vec![BoundarySegment {
+                            edge: Edge::XMinus,
+                            start: 12,
+                            end: 29,
+                            condition: BoundaryConditions::Inflow {
+                                discharge: TimeSeries::Constant(15.0),
+                            },
+                        }],
+                        ..Default::default()
+                    },
+                );
+                // pollutant spilled near the head of the canal
+                let mut spill = Tracer::new(dimensions, 0.05, 0.0, [0.6, 0.9, 0.1]);
+                spill.inject(Vector2::new(40.0, 20.0), 5.0, 50.0);
+                solver.set_tracers(vec![spill]);
+                // floating debris released across the head of the canal
+                solver.set_particles(Some(Particles::new(
+                    Integrator::Rk2,
+                    (13..=27)
+                        .step_by(2)
+                        .map(|y| Vector2::new(2.0, y as f32))
+                        .collect(),
+                    5.0,
+                    Some(500.0),
+                )));
+                // a log drifting with the flow and a boat pushing back on it
+                let mut log =
+                    FloatingBody::new(Vector2::new(20.0, 16.0), Vector2::new(4.0, 0.5), 0.5, 0.6);
+                let mut boat =
+                    FloatingBody::new(Vector2::new(20.0, 24.0), Vector2::new(3.0, 1.5), 0.6, 0.4);
+                boat.two_way = true;
+                for body in [&mut log, &mut boat] {
+                    body.settle(solver.water_h(), solver.ground_h());
+                }
+                solver.set_bodies(vec![log, boat]);
+                (solver, vec![])
+            },
+        },
+        InitialConditions {
+            name: "Warm Over Cold",
+            build_water_fn: || {
+                let dimensions = Vector2::new(100, 100);
+                let (cold, warm, expansion) = (4.0, 25.0, 0.01);
+                let is_warm = |x: usize, y: usize| {
+                    (Vector2::new(x as f32, y as f32) - Vector2::new(50.0, 50.0)).norm() < 15.0
+                };
+                let temperature =
+                    Grid::from_fn(|x, y| if is_warm(x, y) { warm } else { cold }, dimensions);
+                // the lighter warm water stands higher so the pressure at the bed is level
+                let warm_depth = 2.0 / (1.0 - expansion * (warm - cold));
+                let h = Grid::from_fn(
+                    |x, y| if is_warm(x, y) { warm_depth } else { 2.0 },
+                    dimensions,
+                );
+                let g_h = Grid::from_fn(|_, _| 0.0, dimensions);
+                let temperature = Temperature::new(&temperature, &h, cold, expansion, 0.01);
+                let mut solver = T::new(h, g_h, Vec::new(), SolverBoundaryConditions::default());
+                solver.set_temperature(Some(temperature));
+                (solver, vec![])
+            },
+        },
+        InitialConditions {
+            name: "Estuary",
+            build_water_fn: || {
+                let dimensions = Vector2::new(300, 100);
+                let ground_fn = |x: usize, y: usize| {
+                    let slope = -2.0 + 4.0 * x as f32 / 300.0;
+                    let channel = (1.0 - (y as f32 - 50.0).abs() / 10.0).max(0.0);
+                    slope - channel
+                };
+                let g_h = Grid::from_fn(ground_fn, dimensions);
+                let h = Grid::from_fn(|x, y| (-ground_fn(x, y)).max(0.0), dimensions);
+
+                (
+                    T::new(
+                        h,
+                        g_h,
+                        Vec::new(),
+                        SolverBoundaryConditions {
+                            x_minus: BoundaryConditions::Tide {
+                                level: TimeSeries::Sinusoid {
+                                    mean: 0.0,
+                                    amplitude: 1.0,
+                                    period: 2000.0,
+                                },
+                            },
+                            segments: vec![BoundarySegment {
+                                edge: Edge::XPlus,
+                                start: 40,
+                                end: 61,
+                                condition: BoundaryConditions::Inflow {
+                                    discharge: TimeSeries::Constant(10.0),
+                                },
+                            }],
+                            ..Default::default()
+                        },
+                    ),
+                    vec![],
+                )
+            },
+        },
         InitialConditions {
             name: "Lake",
             build_water_fn: || {
@@ -281,6 +922,39 @@ pub fn get_conditions<T: Solver>() -> Vec<InitialConditions<T>> {
                 )
             },
         },
+        InitialConditions {
+            name: "Flash Flood",
+            build_water_fn: || {
+                let dimensions = Vector2::new(200, 200);
+                let g_h = Grid::from_fn(
+                    |x, y| {
+                        let r = ((x as f32 - 100.0).powi(2) + (y as f32 - 100.0).powi(2)).sqrt();
+                        r / 50.0
+                    },
+                    dimensions,
+                );
+                let h = Grid::from_fn(|_, _| 0.0, dimensions);
+                let mut solver = T::new(h, g_h, Vec::new(), SolverBoundaryConditions::default());
+                // storm cell over one side of the bowl with light evaporation everywhere
+                solver.set_precipitation(Some(Precipitation {
+                    rain: Grid::from_fn(
+                        |x, y| {
+                            let r = ((x as f32 - 50.0).powi(2) + (y as f32 - 100.0).powi(2)).sqrt();
+                            if r < 30.0 {
+                                20.0
+                            } else {
+                                0.0
+                            }
+                        },
+                        dimensions,
+                    ),
+                    evaporation: Grid::from_fn(|_, _| 0.5, dimensions),
+                }));
+                solver.set_infiltration(Some(Infiltration::uniform(dimensions, 0.5, 2e-3)));
+
+                (solver, vec![])
+            },
+        },
         InitialConditions {
             name: "Formed Lake",
             build_water_fn: || {
@@ -316,7 +990,7 @@ pub fn get_conditions<T: Solver>() -> Vec<InitialConditions<T>> {
                 let h = Grid::from_fn(|_, _| 2.0, Vector2::new(200, 200));
                 let g_h = Grid::from_fn(|_x, _y| 0.0, Vector2::new(200, 200));
 
-                let sources = vec![Source {
+                let sources = vec![Source::Pulse {
                     center: Vector2::new(100.0, 100.0),
                     height: 2.2,
                     radius: 10.0,
@@ -335,25 +1009,227 @@ pub fn get_conditions<T: Solver>() -> Vec<InitialConditions<T>> {
                 let g_h = Grid::from_fn(|_x, _y| 0.0, Vector2::new(300, 300));
 
                 let sources = vec![
-                    Source {
+                    Source::Pulse {
+                        center: Vector2::new(160.0, 150.0),
+                        height: 2.2,
+                        radius: 5.0,
+                        period: 400.0,
+                    },
+                    Source::Pulse {
+                        center: Vector2::new(140.0, 150.0),
+                        height: 2.2,
+                        radius: 5.0,
+                        period: 400.0,
+                    },
+                ];
+                (
+                    T::new(h, g_h, sources, SolverBoundaryConditions::default()),
+                    vec![],
+                )
+            },
+        },
+        InitialConditions {
+            name: "Two Sources Periodic",
+            build_water_fn: || {
+                let h = Grid::from_fn(|_, _| 2.0, Vector2::new(300, 300));
+                let g_h = Grid::from_fn(|_x, _y| 0.0, Vector2::new(300, 300));
+
+                let sources = vec![
+                    Source::Pulse {
                         center: Vector2::new(160.0, 150.0),
                         height: 2.2,
                         radius: 5.0,
                         period: 400.0,
                     },
-                    Source {
+                    Source::Pulse {
                         center: Vector2::new(140.0, 150.0),
                         height: 2.2,
                         radius: 5.0,
                         period: 400.0,
                     },
                 ];
+                (
+                    T::new(h, g_h, sources, SolverBoundaryConditions::periodic()),
+                    vec![],
+                )
+            },
+        },
+        InitialConditions {
+            name: "Harbour",
+            build_water_fn: || {
+                let dimensions = Vector2::new(200, 200);
+                let h = Grid::from_fn(|_, _| 2.0, dimensions);
+                let g_h = Grid::from_fn(|_x, _y| 0.0, dimensions);
+
+                let sources = vec![
+                    // outlet pipe along the harbour wall
+                    Source::Line {
+                        start: Vector2::new(0.0, 20.0),
+                        end: Vector2::new(0.0, 40.0),
+                        discharge: TimeSeries::Constant(20.0),
+                    },
+                    Source::Point {
+                        center: Vector2::new(150.0, 30.0),
+                        discharge: TimeSeries::Constant(5.0),
+                    },
+                    // pump that switches on after a while
+                    Source::Point {
+                        center: Vector2::new(180.0, 180.0),
+                        discharge: TimeSeries::Table(Arc::new([(500.0, 0.0), (600.0, -25.0)])),
+                    },
+                    // boat sailing back and forth across the harbour
+                    Source::Moving {
+                        x: TimeSeries::Sinusoid {
+                            mean: 0.0,
+                            amplitude: 60.0,
+                            period: 3000.0,
+                        },
+                        y: TimeSeries::Constant(0.0),
+                        source: Box::new(Source::Pulse {
+                            center: Vector2::new(100.0, 100.0),
+                            height: 1.0,
+                            radius: 4.0,
+                            period: 20.0,
+                        }),
+                    },
+                ];
                 (
                     T::new(h, g_h, sources, SolverBoundaryConditions::default()),
                     vec![],
                 )
             },
         },
+        InitialConditions {
+            name: "Obstacles",
+            build_water_fn: || {
+                let dimensions = Vector2::new(200, 200);
+                let h = Grid::from_fn(|_, _| 2.0, dimensions);
+                let g_h = Grid::from_fn(|_x, _y| 0.0, dimensions);
+                let sources = vec![Source::Pulse {
+                    center: Vector2::new(100.0, 30.0),
+                    height: 1.0,
+                    radius: 6.0,
+                    period: 40.0,
+                }];
+                let mut solver = T::new(h, g_h, sources, SolverBoundaryConditions::default());
+                solver.set_obstacles(vec![
+                    Obstacle::Circle {
+                        center: Vector2::new(60.0, 90.0),
+                        radius: 12.0,
+                    },
+                    // breakwater at an angle
+                    Obstacle::Rectangle {
+                        center: Vector2::new(130.0, 100.0),
+                        half_size: Vector2::new(30.0, 3.0),
+                        angle: 0.4,
+                    },
+                    // wedge shaped island
+                    Obstacle::Polygon {
+                        points: vec![
+                            Vector2::new(80.0, 150.0),
+                            Vector2::new(120.0, 140.0),
+                            Vector2::new(100.0, 175.0),
+                        ],
+                    },
+                ]);
+                (solver, vec![])
+            },
+        },
+        InitialConditions {
+            name: "Weir",
+            build_water_fn: || {
+                let dimensions = Vector2::new(100, 200);
+                let h = Grid::from_fn(|_, y| if y < 60 { 3.0 } else { 1.0 }, dimensions);
+                let g_h = Grid::from_fn(|_x, _y| 0.0, dimensions);
+
+                (
+                    T::new(h, g_h, Vec::new(), SolverBoundaryConditions::default()),
+                    vec![
+                        // weir the reservoir spills over
+                        AABBBarrier {
+                            top_right: Vector2::new(100, 80),
+                            bottom_left: Vector2::new(-1, 79),
+                            crest: Some(1.8),
+                            ..Default::default()
+                        },
+                        // porous breakwater further downstream
+                        AABBBarrier {
+                            top_right: Vector2::new(80, 141),
+                            bottom_left: Vector2::new(20, 140),
+                            porosity: 0.3,
+                            ..Default::default()
+                        },
+                    ],
+                )
+            },
+        },
+        InitialConditions {
+            name: "Sluice Gate",
+            build_water_fn: || {
+                let dimensions = Vector2::new(100, 200);
+                let h = Grid::from_fn(|_, y| if y < 80 { 3.0 } else { 1.0 }, dimensions);
+                let g_h = Grid::from_fn(|_x, _y| 0.0, dimensions);
+                let mut solver = T::new(h, g_h, Vec::new(), SolverBoundaryConditions::default());
+                // gate slides aside into the right wall once the simulation has settled
+                solver.set_moving_barriers(vec![MovingBarrier {
+                    offset_x: TimeSeries::Table(Arc::new([(500.0, 0.0), (600.0, 22.0)])),
+                    ..MovingBarrier::new(AABBBarrier {
+                        top_right: Vector2::new(60, 80),
+                        bottom_left: Vector2::new(40, 79),
+                        ..Default::default()
+                    })
+                }]);
+                (
+                    solver,
+                    vec![
+                        AABBBarrier {
+                            top_right: Vector2::new(39, 80),
+                            bottom_left: Vector2::new(-1, 79),
+                            ..Default::default()
+                        },
+                        AABBBarrier {
+                            top_right: Vector2::new(100, 80),
+                            bottom_left: Vector2::new(61, 79),
+                            ..Default::default()
+                        },
+                    ],
+                )
+            },
+        },
+        InitialConditions {
+            name: "Wave Maker",
+            build_water_fn: || {
+                let dimensions = Vector2::new(300, 40);
+                let h = Grid::from_fn(|_, _| 1.0, dimensions);
+                let g_h = Grid::from_fn(|_x, _y| 0.0, dimensions);
+                let mut solver = T::new(
+                    h,
+                    g_h,
+                    Vec::new(),
+                    SolverBoundaryConditions {
+                        x_plus: BoundaryConditions::Sponge {
+                            width: 50,
+                            level: 1.0,
+                        },
+                        ..Default::default()
+                    },
+                );
+                // piston paddle at the end of the tank
+                solver.set_moving_barriers(vec![MovingBarrier {
+                    offset_x: TimeSeries::Sinusoid {
+                        mean: 2.0,
+                        amplitude: 2.0,
+                        period: 40.0,
+                    },
+                    ..MovingBarrier::new(AABBBarrier {
+                        top_right: Vector2::new(1, 40),
+                        bottom_left: Vector2::new(0, -1),
+                        ..Default::default()
+                    })
+                }]);
+                (solver, vec![])
+            },
+        },
         InitialConditions {
             name: "Big Droplet (warning slow)",
             build_water_fn: || {
@@ -410,18 +1286,22 @@ pub fn get_conditions<T: Solver>() -> Vec<InitialConditions<T>> {
                         AABBBarrier {
                             top_right: Vector2::new(20, 50),
                             bottom_left: Vector2::new(15, 45),
+                            ..Default::default()
                         },
                         AABBBarrier {
                             top_right: Vector2::new(45, 50),
                             bottom_left: Vector2::new(40, 45),
+                            ..Default::default()
                         },
                         AABBBarrier {
                             top_right: Vector2::new(70, 50),
                             bottom_left: Vector2::new(65, 45),
+                            ..Default::default()
                         },
                         AABBBarrier {
                             top_right: Vector2::new(95, 50),
                             bottom_left: Vector2::new(90, 45),
+                            ..Default::default()
                         },
                     ],
                 )
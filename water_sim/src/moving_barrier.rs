@@ -0,0 +1,184 @@
+use super::{AABBBarrier, Edge, Grid, TimeSeries};
+use nalgebra::Vector2;
+/// Barrier moved by a script such as a sluice gate or a paddle wave maker. The barrier is
+/// offset from where it starts by a time series along each axis and its porosity and crest may
+/// change over time. Water in cells the barrier moves onto is pushed ahead of it and water next
+/// to the side it moves towards is carried along, as fast as the barrier in the pipe solver and
+/// up to the speed of waves in the finite solver
+#[derive(Clone, Debug)]
+pub struct MovingBarrier {
+    /// barrier with no offset
+    pub barrier: AABBBarrier,
+    /// offset along x in cells at each step
+    pub offset_x: TimeSeries,
+    /// offset along y in cells at each step
+    pub offset_y: TimeSeries,
+    /// porosity at each step, None keeps the porosity of the barrier
+    pub porosity: Option<TimeSeries>,
+    /// crest height at each step, None keeps the crest of the barrier
+    pub crest: Option<TimeSeries>,
+}
+impl MovingBarrier {
+    /// barrier that stays as it is
+    pub fn new(barrier: AABBBarrier) -> Self {
+        Self {
+            barrier,
+            offset_x: TimeSeries::Constant(0.0),
+            offset_y: TimeSeries::Constant(0.0),
+            porosity: None,
+            crest: None,
+        }
+    }
+    /// offset from the start in cells at step t
+    pub fn offset(&self, t: u32) -> Vector2<f32> {
+        Vector2::new(self.offset_x.value(t), self.offset_y.value(t))
+    }
+    /// distance moved in cells from step t to the next
+    pub fn velocity(&self, t: u32) -> Vector2<f32> {
+        self.offset(t + 1) - self.offset(t)
+    }
+    /// barrier at step t, moved to the nearest cell
+    pub fn at(&self, t: u32) -> AABBBarrier {
+        let offset = self.offset(t).map(|offset| offset.round() as i32);
+        AABBBarrier {
+            top_right: self.barrier.top_right + offset,
+            bottom_left: self.barrier.bottom_left + offset,
+            porosity: self
+                .porosity
+                .as_ref()
+                .map_or(self.barrier.porosity, |porosity| {
+                    porosity.value(t).clamp(0.0, 1.0)
+                }),
+            crest: self
+                .crest
+                .as_ref()
+                .map_or(self.barrier.crest, |crest| Some(crest.value(t))),
+        }
+    }
+    /// Moves the water in cells the barrier moved onto from step t - 1 to step t into the first
+    /// cell past the barrier in the direction it moved. Water is left in place if that cell is
+    /// outside of the domain or blocked
+    pub(crate) fn displace(
+        &self,
+        water: &mut Grid<f32>,
+        t: u32,
+        is_blocked: impl Fn(i32, i32) -> bool,
+    ) {
+        if t == 0 {
+            return;
+        }
+        let (before, after) = (self.at(t - 1), self.at(t));
+        let moved = after.bottom_left - before.bottom_left;
+        if moved == Vector2::new(0, 0) {
+            return;
+        }
+        // pushed along the axis it moved furthest along
+        let step = if moved.x.abs() >= moved.y.abs() {
+            Vector2::new(moved.x.signum(), 0)
+        } else {
+            Vector2::new(0, moved.y.signum())
+        };
+        let (dim_x, dim_y) = (water.x() as i32, water.y() as i32);
+        let inside = |x: i32, y: i32| x >= 0 && y >= 0 && x < dim_x && y < dim_y;
+        for x in after.bottom_left.x.max(0)..=after.top_right.x.min(dim_x - 1) {
+            for y in after.bottom_left.y.max(0)..=after.top_right.y.min(dim_y - 1) {
+                if before.contains_point(x, y) {
+                    continue;
+                }
+                let mut target = Vector2::new(x, y);
+                while after.contains_point(target.x, target.y) {
+                    target += step;
+                }
+                if !inside(target.x, target.y) || is_blocked(target.x, target.y) {
+                    continue;
+                }
+                let moved_water = water.get(x as usize, y as usize);
+                *water.get_mut(x as usize, y as usize) = 0.0;
+                *water.get_mut(target.x as usize, target.y as usize) += moved_water;
+            }
+        }
+    }
+    /// Open cells next to the sides of the barrier it moves towards at step t, each with the
+    /// edge of the cell facing away from the barrier and the speed water there is pushed at
+    /// in cells per step
+    pub(crate) fn pushed_cells(
+        &self,
+        t: u32,
+        dim_x: usize,
+        dim_y: usize,
+        is_blocked: impl Fn(i32, i32) -> bool,
+    ) -> Vec<((usize, usize), Edge, f32)> {
+        let barrier = self.at(t);
+        let velocity = self.velocity(t);
+        let (min, max) = (barrier.bottom_left, barrier.top_right);
+        let mut sides = vec![];
+        if velocity.x > 0.0 {
+            sides.extend((min.y..=max.y).map(|y| ((max.x + 1, y), Edge::XPlus, velocity.x)));
+        } else if velocity.x < 0.0 {
+            sides.extend((min.y..=max.y).map(|y| ((min.x - 1, y), Edge::XMinus, -velocity.x)));
+        }
+        if velocity.y > 0.0 {
+            sides.extend((min.x..=max.x).map(|x| ((x, max.y + 1), Edge::YPlus, velocity.y)));
+        } else if velocity.y < 0.0 {
+            sides.extend((min.x..=max.x).map(|x| ((x, min.y - 1), Edge::YMinus, -velocity.y)));
+        }
+        sides
+            .into_iter()
+            .filter(|((x, y), _, _)| {
+                *x >= 0
+                    && *y >= 0
+                    && (*x as usize) < dim_x
+                    && (*y as usize) < dim_y
+                    && !is_blocked(*x, *y)
+            })
+            .map(|((x, y), edge, speed)| ((x as usize, y as usize), edge, speed))
+            .collect()
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    #[test]
+    fn paddle_pushes_water_ahead() {
+        let dimensions = Vector2::new(10, 1);
+        let mut water = Grid::from_fn(|_, _| 1.0, dimensions);
+        let paddle = MovingBarrier {
+            offset_x: TimeSeries::Table(Arc::new([(0.0, 0.0), (4.0, 2.0)])),
+            ..MovingBarrier::new(AABBBarrier {
+                bottom_left: Vector2::new(0, 0),
+                top_right: Vector2::new(1, 0),
+                ..Default::default()
+            })
+        };
+        assert_eq!(paddle.at(2).bottom_left, Vector2::new(1, 0));
+        assert_eq!(paddle.velocity(1), Vector2::new(0.5, 0.0));
+        let blocked = |t: u32| {
+            let barrier = paddle.at(t);
+            move |x: i32, y: i32| barrier.contains_point(x, y)
+        };
+        for t in 1..=4 {
+            paddle.displace(&mut water, t, blocked(t));
+        }
+        // the water of the two cells the paddle moved onto is ahead of it
+        assert_eq!(water.get(2, 0), 0.0);
+        assert_eq!(water.get(3, 0), 0.0);
+        assert_eq!(water.get(4, 0), 3.0);
+        let pushed = paddle.pushed_cells(3, dimensions.x, dimensions.y, blocked(3));
+        assert_eq!(pushed, vec![((4, 0), Edge::XPlus, 0.5)]);
+        // at rest once the table ends
+        assert!(paddle
+            .pushed_cells(4, dimensions.x, dimensions.y, blocked(4))
+            .is_empty());
+    }
+    #[test]
+    fn gate_opens() {
+        let gate = MovingBarrier {
+            porosity: Some(TimeSeries::Table(Arc::new([(500.0, 0.0), (600.0, 1.0)]))),
+            ..MovingBarrier::new(AABBBarrier::default())
+        };
+        assert!(gate.at(0).is_solid());
+        assert_eq!(gate.at(550).porosity, 0.5);
+        assert_eq!(gate.at(700).porosity, 1.0);
+    }
+}
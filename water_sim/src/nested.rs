@@ -0,0 +1,317 @@
+use super::{
+    AABBBarrier, BoundaryConditions, BoundaryError, BoundarySegment, Edge, Grid, Solver,
+    SolverBoundaryConditions,
+};
+use nalgebra::Vector2;
+/// Region of the domain simulated on a finer grid, for resolving small features such as the
+/// slits of a wall without refining the whole domain. The fine grid takes ratio steps for each
+/// step of the coarse grid, which keeps waves moving at the same speed on both as the shallow
+/// water equations do not change when lengths and times are scaled together. Water crossing
+/// the edge of the region is exchanged with the coarse grid by the flux the coarse grid computes
+/// there so no water is gained or lost, and cells of the coarse grid covered by the region are
+/// set to the mean of the fine cells over them after each step
+#[derive(Clone, Debug)]
+pub struct NestedGrid {
+    /// coarse cell at the bottom left corner of the region
+    pub origin: Vector2<usize>,
+    /// size of the region in coarse cells
+    pub size: Vector2<usize>,
+    /// number of fine cells along each side of a coarse cell
+    pub ratio: usize,
+    /// barriers in fine cells from the origin, added to the barriers of the coarse grid
+    pub barriers: Vec<AABBBarrier>,
+}
+impl NestedGrid {
+    pub fn new(origin: Vector2<usize>, size: Vector2<usize>, ratio: usize) -> Self {
+        Self {
+            origin,
+            size,
+            ratio,
+            barriers: Vec::new(),
+        }
+    }
+    pub fn fine_dimensions(&self) -> Vector2<usize> {
+        self.size * self.ratio
+    }
+    /// whether coarse cell (x, y) is covered by the region
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.origin.x..self.origin.x + self.size.x).contains(&x)
+            && (self.origin.y..self.origin.y + self.size.y).contains(&y)
+    }
+    /// fine grid with each coarse cell copied onto the fine cells covering it
+    pub(crate) fn prolong(&self, coarse: &Grid<f32>) -> Grid<f32> {
+        Grid::from_fn(
+            |x, y| {
+                coarse.get(
+                    self.origin.x + x / self.ratio,
+                    self.origin.y + y / self.ratio,
+                )
+            },
+            self.fine_dimensions(),
+        )
+    }
+    /// sets each coarse cell covered by the region to the mean of the fine cells over it
+    pub(crate) fn restrict(&self, fine: &Grid<f32>, coarse: &mut Grid<f32>) {
+        let area = (self.ratio * self.ratio) as f32;
+        let r = self.ratio;
+        for x in 0..self.size.x {
+            for y in 0..self.size.y {
+                let sum = fine
+                    .window(x * r..(x + 1) * r, y * r..(y + 1) * r)
+                    .sum::<f32>();
+                *coarse.get_mut(self.origin.x + x, self.origin.y + y) = sum / area;
+            }
+        }
+    }
+    /// Fine cells along the side of coarse cell (x, y) facing edge
+    pub(crate) fn face_cells(&self, x: usize, y: usize, edge: Edge) -> Vec<(usize, usize)> {
+        let (x, y) = (x - self.origin.x, y - self.origin.y);
+        let r = self.ratio;
+        (0..r)
+            .map(|i| match edge {
+                Edge::XPlus => ((x + 1) * r - 1, y * r + i),
+                Edge::XMinus => (x * r, y * r + i),
+                Edge::YPlus => (x * r + i, (y + 1) * r - 1),
+                Edge::YMinus => (x * r + i, y * r),
+            })
+            .collect()
+    }
+    /// Boundary conditions of the fine grid. Edges of the region inside of the domain are walls
+    /// to the fine grid as water crossing them is exchanged with the coarse grid, edges on the
+    /// edge of the domain keep the conditions of the coarse grid. Conditions that depend on
+    /// the size of cells or on time can not be refined
+    pub(crate) fn boundary_conditions(
+        &self,
+        coarse: &SolverBoundaryConditions,
+        dim_x: usize,
+        dim_y: usize,
+    ) -> Result<SolverBoundaryConditions, BoundaryError> {
+        let mut fine = SolverBoundaryConditions::default();
+        for edge in Edge::ALL {
+            let on_edge = match edge {
+                Edge::XPlus => self.origin.x + self.size.x == dim_x,
+                Edge::XMinus => self.origin.x == 0,
+                Edge::YPlus => self.origin.y + self.size.y == dim_y,
+                Edge::YMinus => self.origin.y == 0,
+            };
+            if !on_edge {
+                continue;
+            }
+            let start = edge.index(self.origin.x, self.origin.y);
+            for i in 0..edge.len(self.size.x, self.size.y) {
+                let condition = coarse.at(edge, start + i);
+                match condition {
+                    BoundaryConditions::Reflect => {}
+                    BoundaryConditions::Absorb
+                    | BoundaryConditions::Ocean { .. }
+                    | BoundaryConditions::FreeOutflow => fine.segments.push(BoundarySegment {
+                        edge,
+                        start: i * self.ratio,
+                        end: (i + 1) * self.ratio,
+                        condition: condition.clone(),
+                    }),
+                    _ => {
+                        return Err(BoundaryError::NotRefinable {
+                            edge,
+                            index: start + i,
+                        })
+                    }
+                }
+            }
+        }
+        Ok(fine)
+    }
+    /// barriers of the coarse grid moved onto the fine grid along with the barriers of the region
+    pub(crate) fn fine_barriers(&self, coarse: &[AABBBarrier]) -> Vec<AABBBarrier> {
+        let origin = self.origin.map(|x| x as i32);
+        let r = self.ratio as i32;
+        coarse
+            .iter()
+            .map(|barrier| AABBBarrier {
+                bottom_left: (barrier.bottom_left - origin) * r,
+                top_right: (barrier.top_right - origin).add_scalar(1) * r - Vector2::new(1, 1),
+                ..*barrier
+            })
+            .chain(self.barriers.iter().copied())
+            .collect()
+    }
+    /// Faces between coarse cells covered by the region and cells outside of it, as the covered
+    /// cell and its side facing out. Faces on the edge of the domain are left to the fine grid
+    /// unless they wrap around to the opposite edge
+    pub(crate) fn interface(
+        &self,
+        boundary_conditions: &SolverBoundaryConditions,
+        dim_x: usize,
+        dim_y: usize,
+    ) -> Vec<((usize, usize), Edge)> {
+        let mut faces = vec![];
+        for x in self.origin.x..self.origin.x + self.size.x {
+            for y in self.origin.y..self.origin.y + self.size.y {
+                for edge in Edge::ALL {
+                    if let Some((n_x, n_y)) =
+                        boundary_conditions.neighbour(edge, dim_x, dim_y, x, y)
+                    {
+                        if !self.contains(n_x, n_y) {
+                            faces.push(((x, y), edge));
+                        }
+                    }
+                }
+            }
+        }
+        faces
+    }
+    /// Adds volume to the fine cells along the side of coarse cell (x, y) facing edge, volume is
+    /// measured in coarse cells. Water flowing in is spread evenly along the side and water
+    /// flowing out is taken in proportion to the water in each fine cell so none go dry.
+    /// Returns the volume added, which is less water out than asked for when the fine cells
+    /// along the side hold less
+    pub(crate) fn exchange(
+        &self,
+        fine: &mut Grid<f32>,
+        x: usize,
+        y: usize,
+        edge: Edge,
+        volume: f32,
+    ) -> f32 {
+        let cells = self.face_cells(x, y, edge);
+        // volume of a coarse cell in fine cells
+        let area = (self.ratio * self.ratio) as f32;
+        if volume >= 0.0 {
+            for (f_x, f_y) in cells {
+                *fine.get_mut(f_x, f_y) += volume * area / self.ratio as f32;
+            }
+            volume
+        } else {
+            let available = cells
+                .iter()
+                .map(|(f_x, f_y)| fine.get(*f_x, *f_y).max(0.0))
+                .sum::<f32>();
+            if available <= 0.0 {
+                return 0.0;
+            }
+            let fraction = (-volume * area / available).min(1.0);
+            for (f_x, f_y) in cells {
+                let water = fine.get_mut(f_x, f_y);
+                *water -= water.max(0.0) * fraction;
+            }
+            -fraction * available / area
+        }
+    }
+}
+/// Region of a solver simulated on a finer grid by a solver of the same kind
+pub(crate) struct Nest<T> {
+    pub grid: NestedGrid,
+    pub fine: Box<T>,
+}
+impl<T: Solver> Nest<T> {
+    /// builds fine grid from the water and ground of the coarse grid
+    pub fn new(
+        grid: NestedGrid,
+        water: &Grid<f32>,
+        ground: &Grid<f32>,
+        boundary_conditions: &SolverBoundaryConditions,
+    ) -> Result<Self, BoundaryError> {
+        let (dim_x, dim_y) = (water.x(), water.y());
+        if grid.ratio == 0 {
+            return Err(BoundaryError::NestedRatioZero);
+        }
+        if grid.origin.x + grid.size.x > dim_x || grid.origin.y + grid.size.y > dim_y {
+            return Err(BoundaryError::NestedOutsideDomain {
+                origin: grid.origin,
+                size: grid.size,
+            });
+        }
+        let fine_conditions = grid.boundary_conditions(boundary_conditions, dim_x, dim_y)?;
+        let fine = T::new(
+            grid.prolong(water),
+            grid.prolong(ground),
+            Vec::new(),
+            fine_conditions,
+        );
+        Ok(Self {
+            grid,
+            fine: Box::new(fine),
+        })
+    }
+    /// Runs the fine grid for one coarse step and restricts its water onto coarse water.
+    /// exchange is the volume entering each interface face over the step and water_mut gives
+    /// the water of the fine solver. The coarse cells outside of the region have already
+    /// exchanged the full volume, water the fine cells could not give is taken back from them
+    pub fn advance(
+        &mut self,
+        boxes: &[AABBBarrier],
+        exchange: &[((usize, usize), Edge, f32)],
+        water: &mut Grid<f32>,
+        boundary_conditions: &SolverBoundaryConditions,
+        water_mut: fn(&mut T) -> &mut Grid<f32>,
+    ) {
+        let boxes = self.grid.fine_barriers(boxes);
+        let steps = self.grid.ratio as f32;
+        let (dim_x, dim_y) = (water.x(), water.y());
+        for _ in 0..self.grid.ratio {
+            for ((x, y), edge, volume) in exchange.iter() {
+                let volume = volume / steps;
+                let added = self
+                    .grid
+                    .exchange(water_mut(&mut self.fine), *x, *y, *edge, volume);
+                if added != volume {
+                    if let Some((n_x, n_y)) =
+                        boundary_conditions.neighbour(*edge, dim_x, dim_y, *x, *y)
+                    {
+                        *water.get_mut(n_x, n_y) += volume - added;
+                    }
+                }
+            }
+            self.fine.solve(&boxes);
+        }
+        self.grid.restrict(self.fine.water_h(), water);
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn restrict_prolong() {
+        let nest = NestedGrid::new(Vector2::new(1, 2), Vector2::new(2, 3), 3);
+        let coarse = Grid::from_fn(|x, y| (x + 10 * y) as f32, Vector2::new(5, 6));
+        let fine = nest.prolong(&coarse);
+        assert_eq!(fine.x(), 6);
+        assert_eq!(fine.y(), 9);
+        assert_eq!(fine.get(5, 8), coarse.get(2, 4));
+        let mut restricted = Grid::from_fn(|_, _| 0.0, Vector2::new(5, 6));
+        nest.restrict(&fine, &mut restricted);
+        assert_eq!(restricted.get(2, 4), coarse.get(2, 4));
+        assert_eq!(restricted.get(0, 0), 0.0);
+        // faces inside of the domain on each side of the region
+        let faces = nest.interface(&SolverBoundaryConditions::default(), 5, 6);
+        assert_eq!(faces.len(), 2 * 2 + 2 * 3);
+        assert!(faces.contains(&((1, 2), Edge::XMinus)));
+        assert!(!faces.contains(&((1, 2), Edge::XPlus)));
+    }
+    #[test]
+    fn exchange_conserves_volume() {
+        let nest = NestedGrid::new(Vector2::new(0, 0), Vector2::new(2, 2), 2);
+        let mut fine = Grid::from_fn(|x, _| if x == 3 { 0.5 } else { 1.0 }, Vector2::new(4, 4));
+        let volume = |fine: &Grid<f32>| fine.sum() / 4.0;
+        let before = volume(&fine);
+        nest.exchange(&mut fine, 1, 0, Edge::XPlus, 0.25);
+        assert!((volume(&fine) - before - 0.25).abs() < 1e-6);
+        assert_eq!(fine.get(3, 0), 1.0);
+        assert_eq!(nest.exchange(&mut fine, 1, 1, Edge::YPlus, -0.25), -0.25);
+        assert!((volume(&fine) - before).abs() < 1e-6);
+        // wetter cells give up more water
+        assert!(fine.get(2, 3) > fine.get(3, 3));
+        // asking for more than the side holds only takes what is there
+        let side = (fine.get(0, 0) + fine.get(0, 1)) / 4.0;
+        let added = nest.exchange(&mut fine, 0, 0, Edge::XMinus, -10.0);
+        assert!((added + side).abs() < 1e-6);
+        assert_eq!(fine.get(0, 0), 0.0);
+        let barriers = nest.fine_barriers(&[AABBBarrier {
+            bottom_left: Vector2::new(1, 0),
+            top_right: Vector2::new(1, 1),
+            ..Default::default()
+        }]);
+        assert_eq!(barriers[0].bottom_left, Vector2::new(2, 0));
+        assert_eq!(barriers[0].top_right, Vector2::new(3, 3));
+    }
+}
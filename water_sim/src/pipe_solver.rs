@@ -1,18 +1,13 @@
 use super::{
-    AABBBarrier, BoundaryConditions, Grid, SolveInfo, Solver, SolverBoundaryConditions, Source,
-    Vector,
+    AABBBarrier, BoundaryConditions, BoundaryError, Coriolis, Edge, Erosion, FloatingBody, Grid,
+    Infiltration, Layers, MassBalance, MovingBarrier, NestedGrid, Obstacle, Particles,
+    Precipitation, SolveInfo, Solver, SolverBoundaryConditions, Source, SourceForm, Temperature,
+    ThermalErosion, Tracer, Vector, Wind,
 };
+use crate::{barrier::ObstacleMask, nested::Nest, sponge::SpongeLayer};
 use bevy::prelude::Component;
-use grid::DebugBuffer;
+use grid::{ActiveTiles, DebugBuffer, TiledGrid};
 use nalgebra::Vector2;
-use std::ops::Neg;
-/// bounds x by negative and positive version of value
-/// for example, max_min(1.0,3.0) -> 1.0
-/// max_min(4.0,3.0)->3.0
-/// max_min(-5.0,3.0)->-3.0
-fn max_min(x: f32, bounds: f32) -> f32 {
-    x.min(bounds.abs()).max(bounds.abs().neg())
-}
 /// used https://github.com/bshishov/UnityTerrainErosionGPU as reference
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -34,6 +29,26 @@ impl Default for Pipes {
     }
 }
 
+impl Pipes {
+    /// outflow through side of cell facing edge
+    fn pipe(&self, edge: Edge) -> f32 {
+        match edge {
+            Edge::XPlus => self.r,
+            Edge::XMinus => self.l,
+            Edge::YPlus => self.u,
+            Edge::YMinus => self.d,
+        }
+    }
+    fn pipe_mut(&mut self, edge: Edge) -> &mut f32 {
+        match edge {
+            Edge::XPlus => &mut self.r,
+            Edge::XMinus => &mut self.l,
+            Edge::YPlus => &mut self.u,
+            Edge::YMinus => &mut self.d,
+        }
+    }
+}
+
 impl Vector for Pipes {
     const DIM: usize = 1;
 
@@ -50,14 +65,33 @@ impl Vector for Pipes {
 pub struct PipeSolver {
     water: Grid<f32>,
     water_debug_buffer: DebugBuffer<f32>,
-    velocity: Grid<Pipes>,
+    velocity: TiledGrid<Pipes>,
     velocity_debug_buffer: DebugBuffer<Vector2<f32>>,
     ground: Grid<f32>,
     ground_debug_buffer: DebugBuffer<f32>,
-    dissolved_ground: Grid<f32>,
-    dissolved_ground_debug_buffer: DebugBuffer<f32>,
+    sediment_debug_buffer: DebugBuffer<f32>,
     sources: Vec<Source>,
     boundary_conditions: SolverBoundaryConditions,
+    coriolis: Coriolis,
+    wind: Option<Wind>,
+    precipitation: Option<Precipitation>,
+    infiltration: Option<Infiltration>,
+    erosion: Option<Erosion>,
+    thermal_erosion: Option<ThermalErosion>,
+    layers: Option<Layers>,
+    tracers: Vec<Tracer>,
+    temperature: Option<Temperature>,
+    particles: Option<Particles>,
+    bodies: Vec<FloatingBody>,
+    moving_barriers: Vec<MovingBarrier>,
+    nest: Option<Nest<PipeSolver>>,
+    mass_balance: MassBalance,
+    source_form: SourceForm,
+    sponge: Option<SpongeLayer>,
+    /// cells blocked by obstacles and barriers
+    obstacles: ObstacleMask,
+    /// tiles with water in or next to them, the rest are dry and skipped
+    active: ActiveTiles,
     t: u32,
 }
 
@@ -68,28 +102,68 @@ impl Solver for PipeSolver {
         sources: Vec<Source>,
         boundary_conditions: SolverBoundaryConditions,
     ) -> Self {
+        boundary_conditions
+            .validate(water.x(), water.y())
+            .expect("invalid boundary conditions");
         let dimensions = Vector2::new(water.x(), water.y());
+        let sponge = SpongeLayer::new(&boundary_conditions, dimensions);
         Self {
             water,
             water_debug_buffer: DebugBuffer::new(Self::DEBUG_INTERVAL as u32),
-            velocity: Grid::from_fn(|_, _| Pipes::default(), dimensions),
+            velocity: TiledGrid::new(dimensions, Self::TILE_SIZE, Pipes::default()),
             velocity_debug_buffer: DebugBuffer::new(Self::DEBUG_INTERVAL as u32),
             ground,
             ground_debug_buffer: DebugBuffer::new(Self::DEBUG_INTERVAL as u32),
-            dissolved_ground: Grid::from_fn(|_, _| 0.0, dimensions),
-            dissolved_ground_debug_buffer: DebugBuffer::new(Self::DEBUG_INTERVAL as u32),
+            sediment_debug_buffer: DebugBuffer::new(Self::DEBUG_INTERVAL as u32),
             sources,
             boundary_conditions,
+            coriolis: Coriolis::None,
+            wind: None,
+            precipitation: None,
+            infiltration: None,
+            erosion: None,
+            thermal_erosion: None,
+            layers: None,
+            tracers: Vec::new(),
+            temperature: None,
+            particles: None,
+            bodies: Vec::new(),
+            moving_barriers: Vec::new(),
+            nest: None,
+            mass_balance: MassBalance::default(),
+            source_form: SourceForm::Height,
+            sponge,
+            obstacles: ObstacleMask::new(dimensions),
+            active: ActiveTiles::new(dimensions, Self::TILE_SIZE),
             t: 0,
         }
     }
 
-    fn solve(&mut self, _boxes: &[AABBBarrier]) -> (&Grid<f32>, Vec<SolveInfo>) {
+    fn solve(&mut self, boxes: &[AABBBarrier]) -> (&Grid<f32>, Vec<SolveInfo>) {
+        let mut boxes = boxes.to_vec();
+        boxes.extend(self.moving_barriers());
+        self.obstacles.update_boxes(&boxes);
         self.solve_pipe();
-        self.solve_erode();
+        self.apply_nest(&boxes);
+        self.apply_particles();
+        self.apply_bodies();
 
         // self.debug_save();
-        (&self.water, vec![])
+        let mut info = if self.precipitation.is_some()
+            || self.infiltration.is_some()
+            || self.source_form == SourceForm::Flux
+        {
+            self.mass_balance.solve_info(self.volume())
+        } else {
+            vec![]
+        };
+        if let Some(erosion) = &self.erosion {
+            info.push(SolveInfo {
+                name: "Sediment",
+                data: format!("{:.2}", erosion.suspended()),
+            });
+        }
+        (&self.water, info)
     }
 
     fn water_h(&self) -> &Grid<f32> {
@@ -109,6 +183,121 @@ impl Solver for PipeSolver {
     fn get_ground_mut(&mut self, x: usize, y: usize) -> &mut f32 {
         self.ground.get_mut(x, y)
     }
+    fn set_coriolis(&mut self, coriolis: Coriolis) {
+        self.coriolis = coriolis;
+    }
+    fn set_wind(&mut self, wind: Option<Wind>) {
+        self.wind = wind;
+    }
+    fn set_precipitation(&mut self, precipitation: Option<Precipitation>) {
+        self.precipitation = precipitation;
+    }
+    fn set_infiltration(&mut self, infiltration: Option<Infiltration>) {
+        self.infiltration = infiltration;
+    }
+    fn infiltration(&self) -> Option<&Infiltration> {
+        self.infiltration.as_ref()
+    }
+    fn set_erosion(&mut self, erosion: Option<Erosion>) {
+        self.erosion = erosion;
+    }
+    fn erosion(&self) -> Option<&Erosion> {
+        self.erosion.as_ref()
+    }
+    fn set_thermal_erosion(&mut self, thermal_erosion: Option<ThermalErosion>) {
+        self.thermal_erosion = thermal_erosion;
+    }
+    fn set_layers(&mut self, layers: Option<Layers>) {
+        self.layers = layers;
+    }
+    fn layers(&self) -> Option<&Layers> {
+        self.layers.as_ref()
+    }
+    fn set_tracers(&mut self, tracers: Vec<Tracer>) {
+        self.tracers = tracers;
+    }
+    fn tracers(&self) -> &[Tracer] {
+        &self.tracers
+    }
+    fn tracers_mut(&mut self) -> &mut [Tracer] {
+        &mut self.tracers
+    }
+    fn set_temperature(&mut self, temperature: Option<Temperature>) {
+        self.temperature = temperature;
+    }
+    fn temperature(&self) -> Option<&Temperature> {
+        self.temperature.as_ref()
+    }
+    fn set_particles(&mut self, particles: Option<Particles>) {
+        self.particles = particles;
+    }
+    fn particles(&self) -> Option<&Particles> {
+        self.particles.as_ref()
+    }
+    fn particles_mut(&mut self) -> Option<&mut Particles> {
+        self.particles.as_mut()
+    }
+    fn set_bodies(&mut self, bodies: Vec<FloatingBody>) {
+        self.bodies = bodies;
+    }
+    fn bodies(&self) -> &[FloatingBody] {
+        &self.bodies
+    }
+    fn bodies_mut(&mut self) -> &mut Vec<FloatingBody> {
+        &mut self.bodies
+    }
+    fn velocity_field(&self) -> Grid<Vector2<f32>> {
+        Grid::from_fn(
+            |x, y| {
+                let depth = self.water.get(x, y);
+                if depth <= 0.0 {
+                    return Vector2::new(0.0, 0.0);
+                }
+                // mean of the discharge through opposite faces
+                let flux = |edge| self.face_flux(x, y, edge);
+                Vector2::new(
+                    (flux(Edge::XPlus) - flux(Edge::XMinus))
+                        / (2.0 * depth * Self::L_Y * Self::L_X),
+                    (flux(Edge::YPlus) - flux(Edge::YMinus))
+                        / (2.0 * depth * Self::L_X * Self::L_Y),
+                )
+            },
+            Vector2::new(self.dim_x(), self.dim_y()),
+        )
+    }
+    fn mass_balance(&self) -> MassBalance {
+        self.mass_balance
+    }
+    fn set_source_form(&mut self, form: SourceForm) {
+        self.source_form = form;
+    }
+    fn set_obstacles(&mut self, obstacles: Vec<Obstacle>) {
+        self.obstacles.set_obstacles(obstacles);
+    }
+    fn obstacle_mask(&self) -> &Grid<bool> {
+        self.obstacles.mask()
+    }
+    fn set_moving_barriers(&mut self, barriers: Vec<MovingBarrier>) {
+        self.moving_barriers = barriers;
+    }
+    fn moving_barriers(&self) -> Vec<AABBBarrier> {
+        self.moving_barriers
+            .iter()
+            .map(|barrier| barrier.at(self.t))
+            .collect()
+    }
+    fn set_nested_grid(&mut self, nest: Option<NestedGrid>) -> Result<(), BoundaryError> {
+        self.nest = None;
+        self.nest = nest
+            .map(|grid| Nest::new(grid, &self.water, &self.ground, &self.boundary_conditions))
+            .transpose()?;
+        Ok(())
+    }
+    fn nested_grid(&self) -> Option<(&NestedGrid, &Grid<f32>)> {
+        self.nest
+            .as_ref()
+            .map(|nest| (&nest.grid, nest.fine.water_h()))
+    }
 }
 
 impl PipeSolver {
@@ -117,6 +306,8 @@ impl PipeSolver {
     const DELTA_T: f32 = 0.1;
     const G: f32 = 9.81;
     const DEBUG_INTERVAL: u32 = 10;
+    /// width of the square tiles dry areas are skipped in
+    const TILE_SIZE: usize = 16;
     fn get_g_h(&self, x: usize, y: usize) -> f32 {
         self.ground.get(x, y)
     }
@@ -132,8 +323,11 @@ impl PipeSolver {
             Vector2::new(self.dim_x(), self.dim_y()),
         ));
         self.ground_debug_buffer.push(self.ground.clone());
-        self.dissolved_ground_debug_buffer
-            .push(self.dissolved_ground.clone());
+        let dimensions = Vector2::new(self.dim_x(), self.dim_y());
+        self.sediment_debug_buffer.push(match &self.erosion {
+            Some(erosion) => erosion.sediment().clone(),
+            None => Grid::from_fn(|_, _| 0.0, dimensions),
+        });
 
         if self.t % Self::DEBUG_INTERVAL == 0 {
             let save_dir = std::path::PathBuf::from("./debug_data");
@@ -143,204 +337,376 @@ impl PipeSolver {
             self.ground_debug_buffer
                 .save(save_dir.as_path().join(&ground_name))
                 .expect("failed to save");
-            let dimensions = Vector2::new(self.dim_x(), self.dim_y());
-            let slope = Grid::from_fn(|x, y| Self::get_slope(&self.ground, x, y), dimensions);
 
             let velocity_name = format!("velocity_{}.npz", self.t);
             self.velocity_debug_buffer
                 .save(save_dir.join(&velocity_name))
                 .expect("failed to save");
 
-            //let slope_name = format!("slope_{}.np", self.t);
-            //slope.debug_save(save_dir.join(&slope_name));
-
             let water_name = format!("water_{}.npz", self.t);
             self.water_debug_buffer
                 .save(save_dir.join(water_name))
                 .expect("failed to save");
 
-            let dissolve_name = format!("dissolved_{}.npz", self.t);
-
-            self.dissolved_ground_debug_buffer
-                .save(save_dir.join(dissolve_name))
+            let sediment_name = format!("sediment_{}.npz", self.t);
+            self.sediment_debug_buffer
+                .save(save_dir.join(sediment_name))
                 .expect("failed to save");
         }
     }
-    fn get_slope(g: &Grid<f32>, x: usize, y: usize) -> f32 {
-        fn abs_max(a: f32, b: f32) -> f32 {
-            if a.abs() >= b.abs() {
-                a
-            } else {
-                b
+    fn get_w_g_h(&self, x: usize, y: usize) -> f32 {
+        self.water.get(x, y) + self.get_g_h(x, y)
+    }
+    /// moves flux of delta from negative pipe to positive pipe, keeping both pipes positive
+    fn shift_flux(positive: &mut f32, negative: &mut f32, delta: f32) {
+        if delta >= 0.0 {
+            let taken = delta.min(*negative);
+            *negative -= taken;
+            *positive += delta - taken;
+        } else {
+            let taken = (-delta).min(*positive);
+            *positive -= taken;
+            *negative += -delta - taken;
+        }
+    }
+    /// rotates net outflow of each cell by the coriolis parameter
+    fn apply_coriolis(&mut self) {
+        if self.coriolis.is_none() {
+            return;
+        }
+        for x in 0..self.velocity.x() {
+            for y in 0..self.velocity.y() {
+                // dry cells have no flow to turn
+                if self.water.get(x, y) == 0.0 {
+                    continue;
+                }
+                let pipe = self.velocity.get_mut(x, y);
+                let flux = Vector2::new(pipe.r - pipe.l, pipe.u - pipe.d);
+                let rotated = self.coriolis.rotate(flux, y as f32, Self::DELTA_T);
+                Self::shift_flux(&mut pipe.r, &mut pipe.l, rotated.x - flux.x);
+                Self::shift_flux(&mut pipe.u, &mut pipe.d, rotated.y - flux.y);
+            }
+        }
+    }
+    /// accelerates outflow of each cell by the surface stress of the wind
+    fn apply_wind(&mut self) {
+        let wind = if let Some(wind) = &self.wind {
+            wind
+        } else {
+            return;
+        };
+        for x in 0..self.velocity.x() {
+            for y in 0..self.velocity.y() {
+                // the kernel empties the pipes of dry cells so they are left alone
+                if self.water.get(x, y) == 0.0 {
+                    continue;
+                }
+                let acceleration =
+                    wind.acceleration(wind.velocity(x, y, self.t), self.water.get(x, y));
+                let delta = Self::DELTA_T * Self::L_X * Self::L_Y * acceleration;
+                let pipe = self.velocity.get_mut(x, y);
+                Self::shift_flux(&mut pipe.r, &mut pipe.l, delta.x);
+                Self::shift_flux(&mut pipe.u, &mut pipe.d, delta.y);
             }
         }
-        let height = g.get(x, y);
-
-        abs_max(
-            (height - g.get_or(x as i32 - 1, y as i32, height)),
-            (height - g.get_or(x as i32 + 1, y as i32, height)),
-        );
-        [
-            (height - g.get_or(x as i32 - 1, y as i32, height)),
-            (height - g.get_or(x as i32 + 1, y as i32, height)),
-            (height - g.get_or(x as i32, y as i32 - 1, height)),
-            (height - g.get_or(x as i32, y as i32 + 1, height)),
-        ]
-        .iter()
-        .fold(0.0, |acc, x| abs_max(acc, *x))
     }
-    fn get_w_g_h(&self, x: usize, y: usize) -> f32 {
-        self.water.get(x, y) + self.get_g_h(x, y)
+    /// pushes water out of the cells moving barriers move onto and sets the outflow of cells
+    /// ahead of them to at least the speed of the barrier
+    fn apply_moving_barriers(&mut self) {
+        let obstacles = &self.obstacles;
+        let (dim_x, dim_y) = (self.water.x(), self.water.y());
+        for barrier in self.moving_barriers.iter() {
+            barrier.displace(&mut self.water, self.t, |x, y| obstacles.is_blocked(x, y));
+            for ((x, y), edge, speed) in
+                barrier.pushed_cells(self.t, dim_x, dim_y, |x, y| obstacles.is_blocked(x, y))
+            {
+                let flux = speed / Self::DELTA_T * self.water.get(x, y) * Self::L_X;
+                let pipe = self.velocity.get_mut(x, y).pipe_mut(edge);
+                *pipe = pipe.max(flux);
+            }
+        }
+    }
+    /// Finds the tiles with water in or next to them and frees the pipes of the other tiles,
+    /// which the kernel would empty as no water leaves dry cells
+    fn update_active_tiles(&mut self) {
+        let (dim_x, dim_y) = (self.dim_x(), self.dim_y());
+        let wrap_x = self.boundary_conditions.wraps(Edge::XMinus, dim_x, dim_y);
+        let wrap_y = self.boundary_conditions.wraps(Edge::YMinus, dim_x, dim_y);
+        let water = &self.water;
+        self.active
+            .update(|x, y| water.get(x, y) != 0.0, wrap_x, wrap_y);
+        for tile in self.active.inactive_tiles() {
+            self.velocity.clear_tile(tile);
+        }
     }
-    fn solve_erode(&mut self) {
-        const GROUND_DELTA_T: f32 = 0.5;
-        let softness = 1.0;
+    /// water plus ground height outside of boundary next to cell (x, y)
+    fn outside_w_g_h(&self, condition: &BoundaryConditions, x: usize, y: usize) -> f32 {
+        match condition {
+            BoundaryConditions::Reflect | BoundaryConditions::Inflow { .. } => self.get_w_g_h(x, y),
+            BoundaryConditions::Absorb | BoundaryConditions::FreeOutflow => self.get_g_h(x, y),
+            BoundaryConditions::Ocean { level } | BoundaryConditions::Sponge { level, .. } => {
+                *level
+            }
+            BoundaryConditions::Tide { level } => level.value(self.t),
+            BoundaryConditions::Periodic => {
+                unreachable!("periodic boundaries have a neighbour inside the domain")
+            }
+        }
+    }
+    /// flux leaving through boundary, walls let nothing through
+    fn boundary_flux(condition: &BoundaryConditions, flux: f32) -> f32 {
+        if condition.is_open() {
+            flux
+        } else {
+            0.0
+        }
+    }
+    /// Water plus ground height of the neighbour of cell (x, y) in the direction of edge. If
+    /// the neighbour is outside of the domain the boundary condition there is used and the pipe
+    /// through the boundary is closed if water can not leave
+    fn neighbour_w_g_h(&self, pipe: &mut Pipes, x: usize, y: usize, edge: Edge) -> f32 {
+        match self
+            .boundary_conditions
+            .neighbour(edge, self.dim_x(), self.dim_y(), x, y)
+        {
+            Some((n_x, n_y)) => self.get_w_g_h(n_x, n_y),
+            None => {
+                let condition = self.boundary_conditions.at(edge, edge.index(x, y));
+                let flux = pipe.pipe_mut(edge);
+                *flux = Self::boundary_flux(condition, *flux);
+                self.outside_w_g_h(condition, x, y)
+            }
+        }
+    }
+    /// scales pipes down so that no more water leaves than the cell holds
+    fn limit_outflow(pipe: &mut Pipes, water: f32) {
+        let total = pipe.l + pipe.r + pipe.u + pipe.d;
+        let max = water.max(0.0) * Self::L_X * Self::L_Y / Self::DELTA_T;
+        if total > max {
+            let k = max / total;
+            pipe.l *= k;
+            pipe.r *= k;
+            pipe.u *= k;
+            pipe.d *= k;
+        }
+    }
+    /// sets flux through free outflow boundaries to the discharge at critical depth, which is
+    /// two thirds of the depth in the cell
+    fn apply_free_outflow(&mut self) {
         let dim_x = self.water.x();
         let dim_y = self.water.y();
-
-        let mut water_new = self.water.clone();
-        let mut new_ground = self.ground.clone();
-        let mut new_dissolved = self.dissolved_ground.clone();
-        for x in 0..dim_x {
-            for y in 0..dim_y {
-                let v_out = self.velocity.get(x, y);
-                let v_out = [v_out.l, v_out.d, v_out.r, v_out.u];
-
-                let v_in = [
-                    if x == 0 {
-                        0.0
-                    } else {
-                        self.velocity.get(x - 1, y).r
-                    },
-                    if x == dim_x - 1 {
-                        0.0
-                    } else {
-                        self.velocity.get(x + 1, y).r
-                    },
-                    if y == 0 {
-                        0.0
-                    } else {
-                        self.velocity.get(x, y - 1).u
-                    },
-                    if y == dim_y - 1 {
-                        0.0
-                    } else {
-                        self.velocity.get(x, y + 1).d
-                    },
-                ];
-                let v = v_out.iter().fold(0.0, |acc, x| acc + x)
-                    + v_in.iter().fold(0.0, |acc, x| acc + x);
-                // max concentration to take
-                let cap = (softness * v).min(0.01) * Self::get_slope(&self.ground, x, y);
-                let cap = (v.max(0.0).atan() * self.water.get(x, y)).max(0.0)
-                    * Self::get_slope(&self.ground, x, y);
-                //let cap = 0.00001;
-                let to_take = (cap - self.dissolved_ground.get(x, y))
-                    * Self::DELTA_T
-                    * GROUND_DELTA_T
-                    * softness;
-
-                //let to_take = to_take.max(-self.water.get(x, y));
-                //let to_take = to_take.max(-self.dissolved_ground.get(x, y));
-                *new_ground.get_mut(x, y) -= to_take;
-                *new_dissolved.get_mut(x, y) += to_take;
-                *water_new.get_mut(x, y) += to_take;
+        for edge in Edge::ALL {
+            for i in 0..edge.len(dim_x, dim_y) {
+                if let BoundaryConditions::FreeOutflow = self.boundary_conditions.at(edge, i) {
+                    let (x, y) = edge.cell(dim_x, dim_y, i);
+                    if self.obstacles.is_blocked(x as i32, y as i32) {
+                        continue;
+                    }
+                    let water = self.water.get(x, y);
+                    // the kernel already emptied the pipes of dry cells, which includes every
+                    // cell of an inactive tile, and writing them would allocate their tiles
+                    if water <= 0.0 {
+                        continue;
+                    }
+                    let critical_depth = 2.0 * water / 3.0;
+                    let pipe = self.velocity.get_mut(x, y);
+                    *pipe.pipe_mut(edge) =
+                        Self::L_X * critical_depth * (Self::G * critical_depth).sqrt();
+                    Self::limit_outflow(pipe, water);
+                }
             }
         }
-        self.ground = new_ground;
-        self.dissolved_ground = new_dissolved;
-        self.water = water_new;
-        let max_move = Grid::from_fn(
-            |x, y| {
-                let velocity = self.velocity.get(x, y);
-                let water = self.water.get(x, y);
-                let velocity_sum = velocity.r + velocity.l + velocity.u + velocity.d;
-                if velocity_sum >= water {
-                    Pipes {
-                        l: water * velocity.l / velocity_sum,
-                        r: water * velocity.r / velocity_sum,
-                        u: water * velocity.u / velocity_sum,
-                        d: water * velocity.d / velocity_sum,
+    }
+    /// Erodes ground and moves sediment with the new flux. Runs before the water is updated so
+    /// that sediment leaves at the concentration of the water the flux is taken from
+    fn apply_erosion(&mut self) {
+        let erosion = if let Some(erosion) = &mut self.erosion {
+            erosion
+        } else {
+            return;
+        };
+        let velocity = &self.velocity;
+        let layers = self.layers.as_ref();
+        let boundary_conditions = &self.boundary_conditions;
+        let (dim_x, dim_y) = (self.water.x(), self.water.y());
+        erosion.erode(
+            &mut self.ground,
+            |x, y| Self::get_velocity(&velocity.get(x, y)).norm(),
+            |x, y| layers.map_or(1.0, |layers| layers.erodibility(x, y)),
+            Self::DELTA_T,
+        );
+        erosion.transport(
+            &self.water,
+            Self::DELTA_T,
+            |x, y, edge| velocity.get(x, y).pipe(edge),
+            |x, y, edge| boundary_conditions.neighbour(edge, dim_x, dim_y, x, y),
+        );
+    }
+    /// collapses slopes steeper than the angle of repose
+    fn apply_thermal_erosion(&mut self) {
+        if let Some(thermal_erosion) = &self.thermal_erosion {
+            let boundary_conditions = &self.boundary_conditions;
+            let (dim_x, dim_y) = (self.ground.x(), self.ground.y());
+            thermal_erosion.apply(
+                &mut self.ground,
+                |x, y, edge| boundary_conditions.neighbour(edge, dim_x, dim_y, x, y),
+                Self::DELTA_T,
+            );
+        }
+    }
+    /// moves tracers and heat with the flow through the pipes
+    fn apply_tracers(&mut self) {
+        let velocity = &self.velocity;
+        let boundary_conditions = &self.boundary_conditions;
+        let (dim_x, dim_y) = (self.water.x(), self.water.y());
+        let outflow = |x, y, edge| velocity.get(x, y).pipe(edge);
+        let neighbour = |x, y, edge| boundary_conditions.neighbour(edge, dim_x, dim_y, x, y);
+        for tracer in self.tracers.iter_mut() {
+            tracer.apply(&self.water, Self::DELTA_T, outflow, neighbour);
+        }
+        if let Some(temperature) = &mut self.temperature {
+            temperature.transport(&self.water, Self::DELTA_T, outflow, neighbour);
+        }
+    }
+    /// net flux out of cell through the face towards edge
+    fn face_flux(&self, x: usize, y: usize, edge: Edge) -> f32 {
+        let inflow = self
+            .boundary_conditions
+            .neighbour(edge, self.dim_x(), self.dim_y(), x, y)
+            .map(|(n_x, n_y)| self.velocity.get(n_x, n_y).pipe(edge.opposite()))
+            .unwrap_or(0.0);
+        self.velocity.get(x, y).pipe(edge) - inflow
+    }
+    /// Runs the nested grid over the step just taken with the water that crossed into it and
+    /// sets the covered cells to the fine grid
+    fn apply_nest(&mut self, boxes: &[AABBBarrier]) {
+        let mut nest = if let Some(nest) = self.nest.take() {
+            nest
+        } else {
+            return;
+        };
+        let (dim_x, dim_y) = (self.dim_x(), self.dim_y());
+        let exchange = nest
+            .grid
+            .interface(&self.boundary_conditions, dim_x, dim_y)
+            .into_iter()
+            .map(|((x, y), edge)| {
+                let volume = -Self::DELTA_T * self.face_flux(x, y, edge) / (Self::L_X * Self::L_Y);
+                ((x, y), edge, volume)
+            })
+            .collect::<Vec<_>>();
+        nest.advance(
+            boxes,
+            &exchange,
+            &mut self.water,
+            &self.boundary_conditions,
+            |fine| &mut fine.water,
+        );
+        // pipes between covered cells carry the flow through the fine cells along them
+        let ratio = nest.grid.ratio as f32;
+        for x in nest.grid.origin.x..nest.grid.origin.x + nest.grid.size.x {
+            for y in nest.grid.origin.y..nest.grid.origin.y + nest.grid.size.y {
+                for edge in Edge::ALL {
+                    let covered = edge
+                        .neighbour(dim_x, dim_y, x, y)
+                        .is_some_and(|(n_x, n_y)| nest.grid.contains(n_x, n_y));
+                    if !covered {
+                        continue;
                     }
-                } else {
-                    velocity
+                    let flux = nest
+                        .grid
+                        .face_cells(x, y, edge)
+                        .into_iter()
+                        .map(|(f_x, f_y)| nest.fine.velocity.get(f_x, f_y).pipe(edge))
+                        .sum::<f32>();
+                    *self.velocity.get_mut(x, y).pipe_mut(edge) = flux / ratio;
                 }
-            },
-            Vector2::new(self.velocity.x(), self.velocity.y()),
-        );
-        let mut new_dissoved_g = self.dissolved_ground.clone();
-
-        let mut water_new = self.water.clone();
-
-        for x in 0..dim_x {
-            for y in 0..dim_y {
-                let d_xm1y0 = if x == 0 {
-                    0.0
-                } else {
-                    self.dissolved_ground.get(x - 1, y)
-                };
-                let d_xp1y0 = if x == dim_x - 1 {
-                    0.0
-                } else {
-                    self.dissolved_ground.get(x + 1, y)
-                };
-                let d_x0ym1 = if y == 0 {
-                    0.0
-                } else {
-                    self.dissolved_ground.get(x, y - 1)
-                };
-
-                let d_x0yp1 = if y == dim_y - 1 {
-                    0.0
-                } else {
-                    self.dissolved_ground.get(x, y + 1)
-                };
-
-                let d_x0y0 = self.dissolved_ground.get(x, y);
-                let v_xm1y0 = if x == 0 {
-                    0.0
-                } else {
-                    max_min(self.velocity.get(x - 1, y).r, max_move.get(x - 1, y).r)
-                };
-                let v_xp1y0 = if x == dim_x - 1 {
-                    0.0
-                } else {
-                    max_min(self.velocity.get(x + 1, y).l, max_move.get(x + 1, y).l)
-                };
-                let v_x0ym1 = if y == 0 {
-                    0.0
-                } else {
-                    max_min(self.velocity.get(x, y - 1).u, max_move.get(x, y - 1).u)
-                };
-                let v_x0yp1 = if y == dim_y - 1 {
-                    0.0
-                } else {
-                    max_min(self.velocity.get(x, y + 1).d, max_move.get(x, y + 1).d)
-                };
-
-                let v_x0y0 = self.velocity.get(x, y);
-
-                let ground_out = d_x0y0
-                    * Self::DELTA_T
-                    * GROUND_DELTA_T
-                    * (v_x0y0.l * Self::L_X
-                        + v_x0y0.r * Self::L_X
-                        + v_x0y0.d * Self::L_Y
-                        + v_x0y0.u * Self::L_Y);
-                let ground_in = Self::DELTA_T
-                    * GROUND_DELTA_T
-                    * (v_xm1y0 * d_xm1y0 * Self::L_X
-                        + v_xp1y0 * d_xp1y0 * Self::L_X
-                        + v_x0ym1 * d_x0ym1 * Self::L_Y
-                        + v_x0yp1 * d_x0yp1 * Self::L_Y);
-                let delta = ground_in - ground_out;
-                *new_dissoved_g.get_mut(x, y) += delta;
-                *water_new.get_mut(x, y) += delta;
             }
         }
-        self.dissolved_ground = new_dissoved_g;
-
-        self.water = water_new;
+        self.nest = Some(nest);
+    }
+    /// moves particles through the flow
+    fn apply_particles(&mut self) {
+        if self.particles.is_none() {
+            return;
+        }
+        let velocity = self.velocity_field();
+        let obstacles = &self.obstacles;
+        if let Some(particles) = &mut self.particles {
+            particles.advance(&velocity, Self::DELTA_T, |x, y| obstacles.is_blocked(x, y));
+        }
+    }
+    /// moves floating bodies and drags the outflow of the water they push back on
+    fn apply_bodies(&mut self) {
+        if self.bodies.is_empty() {
+            return;
+        }
+        let velocity = self.velocity_field();
+        let obstacles = &self.obstacles;
+        for body in self.bodies.iter_mut() {
+            let reaction = body.step(
+                &self.water,
+                &self.ground,
+                &velocity,
+                Self::L_X,
+                Self::DELTA_T,
+                |x, y| obstacles.is_blocked(x, y),
+            );
+            for ((x, y), acceleration) in reaction {
+                let delta = Self::DELTA_T * Self::L_X * self.water.get(x, y) * acceleration;
+                let pipe = self.velocity.get_mut(x, y);
+                Self::shift_flux(&mut pipe.r, &mut pipe.l, delta.x);
+                Self::shift_flux(&mut pipe.u, &mut pipe.d, delta.y);
+                Self::limit_outflow(pipe, self.water.get(x, y));
+            }
+        }
+    }
+    /// damps flow and surface inside of sponge layers
+    fn apply_sponge(&mut self) {
+        let sponge = if let Some(sponge) = &self.sponge {
+            sponge
+        } else {
+            return;
+        };
+        // pipes of inactive tiles are already empty, writing to cells outside of the sponge would
+        // allocate their tiles for nothing
+        for (x, y) in self.active.cells() {
+            let damping = sponge.damping(x, y, Self::DELTA_T);
+            if damping == 1.0 {
+                continue;
+            }
+            let pipe = self.velocity.get_mut(x, y);
+            pipe.l *= damping;
+            pipe.r *= damping;
+            pipe.u *= damping;
+            pipe.d *= damping;
+        }
+        sponge.damp_surface(&mut self.water, &self.ground, Self::DELTA_T);
+    }
+    /// lets water flow in from ocean, tide and sponge boundaries whose level is above the water
+    fn apply_level_inflow(&mut self) {
+        let dim_x = self.water.x();
+        let dim_y = self.water.y();
+        for edge in Edge::ALL {
+            for i in 0..edge.len(dim_x, dim_y) {
+                let condition = self.boundary_conditions.at(edge, i);
+                if !matches!(
+                    condition,
+                    BoundaryConditions::Ocean { .. }
+                        | BoundaryConditions::Tide { .. }
+                        | BoundaryConditions::Sponge { .. }
+                ) {
+                    continue;
+                }
+                let (x, y) = edge.cell(dim_x, dim_y, i);
+                if self.obstacles.is_blocked(x as i32, y as i32) {
+                    continue;
+                }
+                let delta_h = self.outside_w_g_h(&condition, x, y) - self.get_w_g_h(x, y);
+                let flux = 0.0f32.max(Self::DELTA_T * Self::G * Self::L_X * delta_h);
+                *self.water.get_mut(x, y) += Self::DELTA_T * flux / (Self::L_X * Self::L_Y);
+            }
+        }
     }
     fn kernel(
         f_x0y0: Pipes,
@@ -380,391 +746,103 @@ impl PipeSolver {
         }
     }
     fn solve_pipe(&mut self) {
-        for source in self.sources.iter() {
-            source.change_h(&mut self.water, self.t);
+        if self.source_form == SourceForm::Height {
+            for source in self.sources.iter() {
+                source.change_h(&mut self.water, self.t, Self::DELTA_T);
+            }
+        }
+        if let Some(precipitation) = &self.precipitation {
+            precipitation.apply(&mut self.water, &mut self.mass_balance);
         }
+        if let Some(infiltration) = &mut self.infiltration {
+            infiltration.apply(&mut self.water, &mut self.mass_balance);
+        }
+        self.boundary_conditions
+            .apply_inflow(&mut self.water, self.t, Self::DELTA_T);
+        self.apply_coriolis();
+        self.apply_wind();
+        self.apply_moving_barriers();
+        self.update_active_tiles();
+        let cells = self.active.cells().collect::<Vec<_>>();
         let mut new_v = self.velocity.clone();
         let dim_x = self.water.x();
         let dim_y = self.water.y();
-        for x in 1..dim_x - 1 {
-            for y in 1..dim_y - 1 {
-                let t = Self::kernel(
-                    self.velocity.get(x, y),
-                    self.water.get(x, y),
-                    self.get_w_g_h(x, y),
-                    self.get_w_g_h(x - 1, y),
-                    self.get_w_g_h(x + 1, y),
-                    self.get_w_g_h(x, y - 1),
-                    self.get_w_g_h(x, y + 1),
-                );
-                *new_v.get_mut(x, y) = t;
+        for (x, y) in cells.iter().copied() {
+            if self.obstacles.is_blocked(x as i32, y as i32) {
+                *new_v.get_mut(x, y) = Pipes::default();
+                continue;
             }
-        }
-        for y in 1..dim_y - 1 {
-            let mut f = self.velocity.get(0, y);
-            f.l = match self.boundary_conditions.x_minus {
-                BoundaryConditions::Absorb => f.l,
-                BoundaryConditions::Ocean { .. } => f.l,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            *new_v.get_mut(0, y) = match self.boundary_conditions.x_minus {
-                BoundaryConditions::Reflect => Self::kernel(
-                    f,
-                    self.water.get(0, y),
-                    self.get_w_g_h(0, y),
-                    self.get_w_g_h(0, y),
-                    self.get_w_g_h(1, y),
-                    self.get_w_g_h(0, y - 1),
-                    self.get_w_g_h(0, y + 1),
-                ),
-                BoundaryConditions::Ocean { level } => Self::kernel(
-                    f,
-                    self.water.get(0, y),
-                    self.get_w_g_h(0, y),
-                    self.get_w_g_h(0, y),
-                    level,
-                    self.get_w_g_h(0, y - 1),
-                    self.get_w_g_h(0, y + 1),
-                ),
-                BoundaryConditions::Absorb => Self::kernel(
-                    f,
-                    self.water.get(0, y),
-                    self.get_w_g_h(0, y),
-                    self.get_g_h(0, y),
-                    self.get_w_g_h(1, y),
-                    self.get_w_g_h(0, y - 1),
-                    self.get_w_g_h(0, y + 1),
-                ),
-            };
-
-            let mut f = self.velocity.get(dim_x - 1, y);
-            f.r = match self.boundary_conditions.x_plus {
-                BoundaryConditions::Absorb => f.r,
-                BoundaryConditions::Ocean { .. } => f.r,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            *new_v.get_mut(dim_x - 1, y) = match self.boundary_conditions.x_plus {
-                BoundaryConditions::Absorb => Self::kernel(
-                    f,
-                    self.water.get(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 2, y),
-                    self.get_g_h(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 1, y - 1),
-                    self.get_w_g_h(dim_x - 1, y + 1),
-                ),
-                BoundaryConditions::Ocean { level } => Self::kernel(
-                    f,
-                    self.water.get(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 2, y),
-                    level,
-                    self.get_w_g_h(dim_x - 1, y - 1),
-                    self.get_w_g_h(dim_x - 1, y + 1),
-                ),
-                BoundaryConditions::Reflect => Self::kernel(
-                    f,
-                    self.water.get(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 2, y),
-                    self.get_w_g_h(dim_x - 1, y),
-                    self.get_w_g_h(dim_x - 1, y - 1),
-                    self.get_w_g_h(dim_x - 1, y + 1),
-                ),
-            };
-        }
-        for x in 1..dim_x - 2 {
-            let mut f = self.velocity.get(x, 0);
-            f.d = match self.boundary_conditions.y_minus {
-                BoundaryConditions::Absorb => f.d,
-                BoundaryConditions::Ocean { .. } => f.d,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            *new_v.get_mut(x, 0) = match self.boundary_conditions.y_minus {
-                BoundaryConditions::Absorb => Self::kernel(
-                    f,
-                    self.water.get(x, 0),
-                    self.get_w_g_h(x, 0),
-                    self.get_w_g_h(x - 1, 0),
-                    self.get_w_g_h(x + 1, 0),
-                    self.get_g_h(x, 0),
-                    self.get_w_g_h(x, 1),
-                ),
-                BoundaryConditions::Ocean { level } => Self::kernel(
-                    f,
-                    self.water.get(x, 0),
-                    self.get_w_g_h(x, 0),
-                    self.get_w_g_h(x - 1, 0),
-                    self.get_w_g_h(x + 1, 0),
-                    level,
-                    self.get_w_g_h(x, 1),
-                ),
-                BoundaryConditions::Reflect => Self::kernel(
-                    f,
-                    self.water.get(x, 0),
-                    self.get_w_g_h(x, 0),
-                    self.get_w_g_h(x - 1, 0),
-                    self.get_w_g_h(x + 1, 0),
-                    self.get_w_g_h(x, 0),
-                    self.get_w_g_h(x, 1),
-                ),
-            };
-
-            let mut f = self.velocity.get(x, dim_y - 1);
-            f.u = match self.boundary_conditions.y_plus {
-                BoundaryConditions::Reflect => 0.0,
-                BoundaryConditions::Ocean { .. } => f.u,
-                BoundaryConditions::Absorb => f.u,
-            };
-
-            *new_v.get_mut(x, dim_y - 1) = match self.boundary_conditions.y_plus {
-                BoundaryConditions::Absorb => Self::kernel(
-                    f,
-                    self.water.get(x, dim_y - 1),
-                    self.get_w_g_h(x, dim_y - 1),
-                    self.get_w_g_h(x - 1, dim_y - 1),
-                    self.get_w_g_h(x + 1, dim_y - 1),
-                    self.get_w_g_h(x, dim_y - 2),
-                    self.get_g_h(x, dim_y - 1),
-                ),
-                BoundaryConditions::Ocean { level } => Self::kernel(
-                    f,
-                    self.water.get(x, dim_y - 1),
-                    self.get_w_g_h(x, dim_y - 1),
-                    self.get_w_g_h(x - 1, dim_y - 1),
-                    self.get_w_g_h(x + 1, dim_y - 1),
-                    self.get_w_g_h(x, dim_y - 2),
-                    level,
-                ),
-                BoundaryConditions::Reflect => Self::kernel(
-                    f,
-                    self.water.get(x, dim_y - 1),
-                    self.get_w_g_h(x, dim_y - 1),
-                    self.get_w_g_h(x - 1, dim_y - 1),
-                    self.get_w_g_h(x + 1, dim_y - 1),
-                    self.get_w_g_h(x, dim_y - 2),
-                    self.get_w_g_h(x, dim_y - 1),
-                ),
-            };
-        }
-        {
-            let mut f = self.velocity.get(0, 0);
-            f.d = match self.boundary_conditions.y_minus {
-                BoundaryConditions::Absorb => f.d,
-                BoundaryConditions::Ocean { .. } => f.d,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            f.l = match self.boundary_conditions.x_minus {
-                BoundaryConditions::Absorb => f.l,
-                BoundaryConditions::Ocean { .. } => f.l,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            let wg_xm1y0 = match self.boundary_conditions.x_minus {
-                BoundaryConditions::Absorb => self.get_g_h(0, 0),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(0, 0),
-            };
-            let wg_x0ym1 = match self.boundary_conditions.y_minus {
-                BoundaryConditions::Absorb => self.get_g_h(0, 0),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(0, 0),
-            };
-            *new_v.get_mut(0, 0) = Self::kernel(
+            let mut f = self.velocity.get(x, y);
+            let mut neighbours = Edge::ALL.map(|edge| self.neighbour_w_g_h(&mut f, x, y, edge));
+            if let Some(temperature) = &self.temperature {
+                // differences in density change the head driving each pipe
+                for (edge, w_g_h) in Edge::ALL.iter().zip(neighbours.iter_mut()) {
+                    if let Some(neighbour) = self
+                        .boundary_conditions
+                        .neighbour(*edge, dim_x, dim_y, x, y)
+                    {
+                        *w_g_h = self.get_w_g_h(x, y)
+                            - temperature.head_difference(
+                                (x, y),
+                                neighbour,
+                                &self.water,
+                                &self.ground,
+                            );
+                    }
+                }
+            }
+            let [wg_xp1y0, wg_xm1y0, wg_x0yp1, wg_x0ym1] = neighbours;
+            let mut new_f = Self::kernel(
                 f,
-                self.water.get(0, 0),
-                self.get_w_g_h(0, 0),
+                self.water.get(x, y),
+                self.get_w_g_h(x, y),
                 wg_xm1y0,
-                self.get_w_g_h(1, 0),
-                wg_x0ym1,
-                self.get_w_g_h(0, 1),
-            );
-        }
-        {
-            let mut f = self.velocity.get(dim_x - 1, 0);
-            f.d = match self.boundary_conditions.y_minus {
-                BoundaryConditions::Absorb => f.d,
-                BoundaryConditions::Ocean { .. } => f.d,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            f.r = match self.boundary_conditions.x_plus {
-                BoundaryConditions::Absorb => f.r,
-                BoundaryConditions::Ocean { .. } => f.r,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            let wg_xp1y0 = match self.boundary_conditions.x_plus {
-                BoundaryConditions::Absorb => self.get_g_h(dim_x - 1, 0),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(dim_x - 1, 0),
-            };
-            let wg_x0ym1 = match self.boundary_conditions.y_minus {
-                BoundaryConditions::Absorb => self.get_g_h(dim_x - 1, 0),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(dim_x - 1, 0),
-            };
-            *new_v.get_mut(dim_x - 1, 0) = Self::kernel(
-                f,
-                self.water.get(dim_x - 1, 0),
-                self.get_w_g_h(dim_x - 1, 0),
-                self.get_w_g_h(dim_x - 2, 0),
                 wg_xp1y0,
                 wg_x0ym1,
-                self.get_w_g_h(dim_x - 1, 1),
-            );
-        }
-        {
-            let mut f = self.velocity.get(dim_x - 1, dim_y - 1);
-            f.u = match self.boundary_conditions.y_plus {
-                BoundaryConditions::Absorb => f.u,
-                BoundaryConditions::Ocean { .. } => f.u,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            f.r = match self.boundary_conditions.x_plus {
-                BoundaryConditions::Absorb => f.r,
-                BoundaryConditions::Ocean { .. } => f.r,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            let wg_xp1y0 = match self.boundary_conditions.x_plus {
-                BoundaryConditions::Absorb => self.get_g_h(dim_x - 1, dim_y - 1),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(dim_x - 2, dim_y - 1),
-            };
-            let wg_x0yp1 = match self.boundary_conditions.y_plus {
-                BoundaryConditions::Absorb => self.get_g_h(dim_x - 1, dim_y - 1),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(dim_x - 1, dim_y - 1),
-            };
-            *new_v.get_mut(dim_x - 1, dim_y - 1) = Self::kernel(
-                f,
-                self.water.get(dim_x - 1, dim_y - 1),
-                self.get_w_g_h(dim_x - 1, dim_y - 1),
-                self.get_w_g_h(dim_x - 2, dim_y - 1),
-                wg_xp1y0,
-                self.get_w_g_h(dim_x - 1, dim_y - 2),
-                wg_x0yp1,
-            );
-        }
-        {
-            let mut f = self.velocity.get(0, dim_y - 1);
-            f.u = match self.boundary_conditions.y_plus {
-                BoundaryConditions::Absorb => f.u,
-                BoundaryConditions::Ocean { .. } => f.u,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            f.l = match self.boundary_conditions.x_minus {
-                BoundaryConditions::Absorb => f.l,
-                BoundaryConditions::Ocean { .. } => f.l,
-                BoundaryConditions::Reflect => 0.0,
-            };
-            let wg_xm1y0 = match self.boundary_conditions.x_minus {
-                BoundaryConditions::Absorb => self.get_g_h(0, dim_y - 1),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(0, dim_y - 1),
-            };
-            let wg_x0yp1 = match self.boundary_conditions.y_plus {
-                BoundaryConditions::Absorb => self.get_g_h(0, dim_y - 1),
-                BoundaryConditions::Ocean { level } => level,
-                BoundaryConditions::Reflect => self.get_w_g_h(0, dim_y - 1),
-            };
-            *new_v.get_mut(0, dim_y - 1) = Self::kernel(
-                f,
-                self.water.get(0, dim_y - 1),
-                self.get_w_g_h(0, dim_y - 1),
-                wg_xm1y0,
-                self.get_w_g_h(1, dim_y - 1),
-                self.get_w_g_h(0, dim_y - 2),
                 wg_x0yp1,
             );
+            // pipes into and out of barriers are narrowed to the opening of the barrier
+            for edge in Edge::ALL {
+                if let Some(neighbour) =
+                    self.boundary_conditions.neighbour(edge, dim_x, dim_y, x, y)
+                {
+                    *new_f.pipe_mut(edge) *=
+                        self.obstacles
+                            .opening((x, y), neighbour, &self.water, &self.ground);
+                }
+            }
+            *new_v.get_mut(x, y) = new_f;
         }
 
         self.velocity = new_v;
-
-        for x in 1..dim_x - 1 {
-            for y in 1..dim_y - 1 {
-                let f_out = self.velocity.get(x, y);
-                let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-                let f_in = self.velocity.get(x - 1, y).r
-                    + self.velocity.get(x + 1, y).l
-                    + self.velocity.get(x, y - 1).u
-                    + self.velocity.get(x, y + 1).d;
-                let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-                *self.water.get_mut(x, y) += volume_change
-            }
-            {
-                let f_out = self.velocity.get(x, 0);
-                let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-                let f_in = self.velocity.get(x - 1, 0).r
-                    + self.velocity.get(x + 1, 0).l
-                    + 0.0
-                    + self.velocity.get(x, 1).d;
-                let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-                *self.water.get_mut(x, 0) += volume_change
-            }
-            {
-                let f_out = self.velocity.get(x, dim_y - 1);
-                let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-                let f_in = self.velocity.get(x - 1, dim_y - 1).r
-                    + self.velocity.get(x + 1, dim_y - 1).l
-                    + self.velocity.get(x, dim_y - 2).u
-                    + 0.0;
-                let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-                *self.water.get_mut(x, dim_y - 1) += volume_change
-            }
-        }
-        for y in 1..dim_y - 1 {
-            {
-                let f_out = self.velocity.get(0, y);
-                let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-                let f_in = 0.0
-                    + self.velocity.get(1, y).l
-                    + self.velocity.get(0, y - 1).u
-                    + self.velocity.get(0, y + 1).d;
-                let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-                *self.water.get_mut(0, y) += volume_change;
-            }
-            {
-                let f_out = self.velocity.get(dim_x - 1, y);
-                let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-                let f_in = self.velocity.get(dim_x - 2, y).r
-                    + 0.0
-                    + self.velocity.get(dim_x - 1, y - 1).u
-                    + self.velocity.get(dim_x - 1, y + 1).d;
-                let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-                *self.water.get_mut(dim_x - 1, y) += volume_change
-            }
+        self.apply_free_outflow();
+        self.apply_erosion();
+        self.apply_thermal_erosion();
+        if let Some(layers) = &mut self.layers {
+            layers.sync(&self.ground);
         }
-        {
-            let f_out = self.velocity.get(0, 0);
-            let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-            let f_in = 0.0 + self.velocity.get(1, 0).l + 0.0 + self.velocity.get(0, 1).d;
-            let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-            *self.water.get_mut(0, 0) += volume_change
-        }
-        {
-            let f_out = self.velocity.get(dim_x - 1, 0);
-            let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-            let f_in =
-                self.velocity.get(dim_x - 2, 0).r + 0.0 + 0.0 + self.velocity.get(dim_x - 1, 1).d;
-            let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-            *self.water.get_mut(dim_x - 1, 0) += volume_change
-        }
-        {
-            let f_out = self.velocity.get(dim_x - 1, dim_y - 1);
+        self.apply_tracers();
+
+        for (x, y) in cells {
+            let f_out = self.velocity.get(x, y);
             let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-            let f_in = self.velocity.get(dim_x - 2, dim_y - 1).r
-                + 0.0
-                + self.velocity.get(dim_x - 1, dim_y - 2).u
-                + 0.0;
+            let f_in: f32 = Edge::ALL
+                .iter()
+                .filter_map(|edge| {
+                    self.boundary_conditions
+                        .neighbour(*edge, dim_x, dim_y, x, y)
+                        .map(|(n_x, n_y)| self.velocity.get(n_x, n_y).pipe(edge.opposite()))
+                })
+                .sum();
             let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-            *self.water.get_mut(dim_x - 1, dim_y - 1) += volume_change
+            *self.water.get_mut(x, y) += volume_change
         }
-        {
-            let f_out = self.velocity.get(0, dim_y - 1);
-            let f_out = f_out.l + f_out.u + f_out.d + f_out.r;
-            let f_in =
-                0.0 + self.velocity.get(1, dim_y - 1).l + self.velocity.get(0, dim_y - 2).u + 0.0;
-            let volume_change = Self::DELTA_T * (f_in - f_out) / (Self::L_X * Self::L_Y);
-            *self.water.get_mut(0, dim_y - 1) += volume_change
+        if self.source_form == SourceForm::Flux {
+            self.mass_balance.sources +=
+                Source::apply_flux(&self.sources, &mut self.water, self.t, Self::DELTA_T);
         }
+        self.apply_level_inflow();
+        self.apply_sponge();
 
         self.t += 1;
     }
@@ -773,7 +851,521 @@ impl PipeSolver {
 mod test {
     use super::*;
     #[test]
-    fn min_max() {
-        assert!((max_min(1.0, 2.0) - 1.0).abs() < 0.01)
+    fn shift_flux() {
+        let mut positive = 1.0;
+        let mut negative = 0.5;
+        PipeSolver::shift_flux(&mut positive, &mut negative, 1.0);
+        assert!((positive - 1.5).abs() < 1e-6);
+        assert!(negative.abs() < 1e-6);
+        PipeSolver::shift_flux(&mut positive, &mut negative, -2.0);
+        assert!(positive.abs() < 1e-6);
+        assert!((negative - 0.5).abs() < 1e-6);
+    }
+    #[test]
+    fn segment_boundaries() {
+        use crate::{BoundarySegment, TimeSeries};
+        let dimensions = Vector2::new(20, 10);
+        let boundary_conditions = SolverBoundaryConditions {
+            segments: vec![
+                BoundarySegment {
+                    edge: Edge::XMinus,
+                    start: 2,
+                    end: 6,
+                    condition: BoundaryConditions::Inflow {
+                        discharge: TimeSeries::Constant(1.0),
+                    },
+                },
+                BoundarySegment {
+                    edge: Edge::XPlus,
+                    start: 0,
+                    end: 1,
+                    condition: BoundaryConditions::FreeOutflow,
+                },
+            ],
+            ..Default::default()
+        };
+        assert!(matches!(
+            boundary_conditions.at(Edge::XMinus, 1),
+            BoundaryConditions::Reflect
+        ));
+        assert!(matches!(
+            boundary_conditions.at(Edge::XMinus, 2),
+            BoundaryConditions::Inflow { .. }
+        ));
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            boundary_conditions,
+        );
+        for _ in 0..10 {
+            solver.solve(&[]);
+        }
+        // water only leaves through the corner cell
+        let outflow = solver.velocity.get(19, 0);
+        assert!(outflow.r > 0.0);
+        assert_eq!(solver.velocity.get(19, 1).r, 0.0);
+        assert_eq!(outflow.d, 0.0);
+        assert!(solver.volume() < 200.0 + 10.0 * PipeSolver::DELTA_T);
+    }
+    #[test]
+    fn inertial_oscillation() {
+        // periodic so the flow has no walls to run into
+        let dimensions = Vector2::new(20, 20);
+        let f = 0.1;
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::periodic(),
+        );
+        solver.set_coriolis(Coriolis::Constant { f });
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                solver.velocity.get_mut(x, y).r = 0.01;
+            }
+        }
+        let expected_period = 2.0 * std::f32::consts::PI / (f * PipeSolver::DELTA_T);
+        // records steps where the flux along x crosses zero going negative
+        let mut crossings = vec![];
+        let flux = |solver: &PipeSolver| {
+            let pipe = solver.velocity.get(10, 10);
+            Vector2::new(pipe.r - pipe.l, pipe.u - pipe.d)
+        };
+        let mut last = flux(&solver);
+        for step in 0..(2.0 * expected_period) as usize {
+            solver.solve(&[]);
+            let flux = flux(&solver);
+            if step == (expected_period / 4.0) as usize {
+                // turned clockwise from +x towards -y
+                assert!(flux.y < -0.009, "flux: {}", flux);
+            }
+            if last.x > 0.0 && flux.x <= 0.0 {
+                crossings.push(step);
+            }
+            last = flux;
+        }
+        assert_eq!(crossings.len(), 2);
+        let period = (crossings[1] - crossings[0]) as f32;
+        assert!(
+            (period - expected_period).abs() < 2.0,
+            "period: {} expected: {}",
+            period,
+            expected_period
+        );
+    }
+    #[test]
+    fn periodic() {
+        let dimensions = Vector2::new(20, 10);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|x, _| if x == 0 { 2.0 } else { 1.0 }, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::periodic(),
+        );
+        let volume = solver.volume();
+        for _ in 0..10 {
+            solver.solve(&[]);
+        }
+        // bump spreads across the boundary to the last column
+        assert!(solver.water.get(19, 5) > 1.01);
+        assert!((solver.volume() - volume).abs() < 1e-3);
+        let unmatched = SolverBoundaryConditions {
+            x_plus: BoundaryConditions::Periodic,
+            ..Default::default()
+        };
+        assert!(unmatched.validate(20, 10).is_err());
+    }
+    #[test]
+    fn obstacles() {
+        let dimensions = Vector2::new(20, 20);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(
+                |x, y| {
+                    if x > y + 1 {
+                        1.0 + x as f32 / 10.0
+                    } else {
+                        1.0
+                    }
+                },
+                dimensions,
+            ),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        // diagonal wall blocks cells with |x - y| <= 1, splitting the domain in two
+        solver.set_obstacles(vec![Obstacle::Rectangle {
+            center: Vector2::new(9.5, 9.5),
+            half_size: Vector2::new(20.0, 1.0),
+            angle: std::f32::consts::FRAC_PI_4,
+        }]);
+        let volume = solver.volume();
+        for _ in 0..50 {
+            solver.solve(&[]);
+        }
+        for x in 0..dimensions.x {
+            for y in x.saturating_sub(1)..dimensions.y {
+                assert_eq!(solver.water.get(x, y), 1.0);
+            }
+        }
+        assert!(solver.water.get(19, 0) < 2.9);
+        assert!((solver.volume() - volume).abs() < 1e-3);
+    }
+    /// volume downstream of barrier at x = 15 after a dam at x < 10 is released
+    fn through_barrier(porosity: f32, crest: Option<f32>) -> f32 {
+        let dimensions = Vector2::new(30, 1);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|x, _| if x < 10 { 2.0 } else { 1.0 }, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        let barrier = AABBBarrier {
+            bottom_left: Vector2::new(15, 0),
+            top_right: Vector2::new(15, 0),
+            porosity,
+            crest,
+        };
+        let volume = solver.volume();
+        for _ in 0..200 {
+            solver.solve(&[barrier]);
+        }
+        assert!((solver.volume() - volume).abs() < 1e-3);
+        (16..30).map(|x| solver.water.get(x, 0)).sum::<f32>() - 14.0
+    }
+    #[test]
+    fn porous_barriers() {
+        assert_eq!(through_barrier(0.0, None), 0.0);
+        let porous = through_barrier(0.3, None);
+        let open = through_barrier(1.0, None);
+        assert!(porous > 0.0 && porous < open);
+        // surge from the dam overtops the low weir but not the high one
+        assert!(through_barrier(0.0, Some(1.2)) > 0.0);
+        assert_eq!(through_barrier(0.0, Some(1.8)), 0.0);
+    }
+    #[test]
+    fn erosion_conserves_mass() {
+        let dimensions = Vector2::new(30, 10);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|x, _| if x < 10 { 2.0 } else { 0.5 }, dimensions),
+            Grid::from_fn(|x, y| 1.0 - x as f32 / 30.0 + y as f32 / 100.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        solver.set_erosion(Some(Erosion::uniform(dimensions, 0.1, 0.5, 0.5, 0.2)));
+        let volume = solver.volume();
+        let ground = solver.ground.sum();
+        let initial_ground = solver.ground.clone();
+        for _ in 0..200 {
+            solver.solve(&[]);
+            let erosion = solver.erosion().unwrap();
+            assert!((solver.ground.sum() + erosion.suspended() - ground).abs() < 1e-3);
+            assert!((solver.volume() - volume).abs() < 1e-3);
+        }
+        assert!((solver.ground.get(10, 5) - initial_ground.get(10, 5)).abs() > 1e-4);
+    }
+    #[test]
+    fn tracer_follows_flow() {
+        let dimensions = Vector2::new(30, 10);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|x, _| if x < 10 { 2.0 } else { 0.5 }, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        let mut tracer = Tracer::new(dimensions, 0.0, 0.0, [1.0, 0.0, 0.0]);
+        tracer.inject(Vector2::new(5.0, 5.0), 3.0, 10.0);
+        solver.set_tracers(vec![tracer]);
+        for _ in 0..200 {
+            solver.solve(&[]);
+            assert!((solver.tracers()[0].total() - 10.0).abs() < 1e-3);
+        }
+        // carried by the dam break
+        assert!(solver.tracers()[0].mass().get(15, 5) > 0.0);
+    }
+    #[test]
+    fn particles_follow_flow() {
+        let dimensions = Vector2::new(30, 10);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|x, _| if x < 10 { 2.0 } else { 0.5 }, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        let mut particles = Particles::new(crate::Integrator::Rk4, vec![], 0.0, None);
+        particles.seed(Vector2::new(10.0, 5.0));
+        solver.set_particles(Some(particles));
+        for _ in 0..50 {
+            solver.solve(&[]);
+        }
+        // carried downstream by the dam break
+        let position = solver.particles().unwrap().particles()[0].position;
+        assert!(position.x > 11.0);
+        assert!((position.y - 5.0).abs() < 1e-3);
+    }
+    #[test]
+    fn bodies_drift_with_flow() {
+        let dimensions = Vector2::new(30, 10);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|x, _| if x < 10 { 2.0 } else { 0.5 }, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        let mut body = FloatingBody::new(Vector2::new(10.0, 5.0), Vector2::new(1.0, 1.0), 0.5, 0.5);
+        body.settle(solver.water_h(), solver.ground_h());
+        body.two_way = true;
+        solver.set_bodies(vec![body]);
+        let volume = solver.volume();
+        for _ in 0..50 {
+            solver.solve(&[]);
+        }
+        // carried downstream by the dam break without changing the amount of water
+        let body = solver.bodies()[0];
+        assert!(body.position.x > 11.0);
+        assert!((body.position.y - 5.0).abs() < 1e-3);
+        assert!(body.elevation > 0.0);
+        assert!((solver.volume() - volume).abs() < 1e-2);
+    }
+    #[test]
+    fn paddle_makes_waves() {
+        use crate::{MovingBarrier, TimeSeries};
+        let dimensions = Vector2::new(60, 1);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions::default(),
+        );
+        solver.set_moving_barriers(vec![MovingBarrier {
+            offset_x: TimeSeries::Sinusoid {
+                mean: 2.0,
+                amplitude: 2.0,
+                period: 40.0,
+            },
+            ..MovingBarrier::new(AABBBarrier {
+                bottom_left: Vector2::new(0, 0),
+                top_right: Vector2::new(1, 0),
+                ..Default::default()
+            })
+        }]);
+        let volume = solver.volume();
+        let mut max_surface = 0.0f32;
+        for _ in 0..200 {
+            solver.solve(&[]);
+            max_surface = max_surface.max(solver.water_h().get(30, 0));
+        }
+        assert!(max_surface > 1.05);
+        assert!((solver.volume() - volume).abs() < 1e-3);
+    }
+    #[test]
+    fn nested_grid() {
+        use crate::NestedGrid;
+        let dimensions = Vector2::new(60, 1);
+        let build = || {
+            PipeSolver::new(
+                Grid::from_fn(|x, _| if x < 15 { 2.0 } else { 1.0 }, dimensions),
+                Grid::from_fn(|_, _| 0.0, dimensions),
+                Vec::new(),
+                SolverBoundaryConditions::default(),
+            )
+        };
+        let mut reference = build();
+        let mut solver = build();
+        // invalid regions are rejected
+        let nest = |x, size_x, ratio| {
+            Some(NestedGrid::new(
+                Vector2::new(x, 0),
+                Vector2::new(size_x, 1),
+                ratio,
+            ))
+        };
+        assert!(matches!(
+            solver.set_nested_grid(nest(50, 20, 3)),
+            Err(BoundaryError::NestedOutsideDomain { .. })
+        ));
+        assert!(matches!(
+            solver.set_nested_grid(nest(20, 20, 0)),
+            Err(BoundaryError::NestedRatioZero)
+        ));
+        let mut inflow = PipeSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions {
+                x_minus: BoundaryConditions::Inflow {
+                    discharge: crate::TimeSeries::Constant(1.0),
+                },
+                ..Default::default()
+            },
+        );
+        assert!(matches!(
+            inflow.set_nested_grid(nest(0, 10, 3)),
+            Err(BoundaryError::NotRefinable { .. })
+        ));
+        assert!(solver.nested_grid().is_none());
+        solver.set_nested_grid(nest(20, 20, 3)).unwrap();
+        let volume = solver.volume();
+        for _ in 0..100 {
+            reference.solve(&[]);
+            solver.solve(&[]);
+        }
+        // the wave crosses the fine grid and comes out the other side as on one grid, with a
+        // sharper front
+        assert!(solver.water_h().get(45, 0) > 1.1);
+        for x in 0..60 {
+            assert!((solver.water_h().get(x, 0) - reference.water_h().get(x, 0)).abs() < 0.1);
+        }
+        assert!((solver.volume() - volume).abs() < 1e-3);
+        let (nest, fine) = solver.nested_grid().unwrap();
+        assert_eq!(fine.x(), nest.fine_dimensions().x);
+    }
+    #[test]
+    fn dry_tiles_skipped() {
+        // pool in the corner of a dry slope
+        let dimensions = Vector2::new(64, 64);
+        let build = || {
+            PipeSolver::new(
+                Grid::from_fn(|x, y| if x < 8 && y < 8 { 2.0 } else { 0.0 }, dimensions),
+                Grid::from_fn(|x, y| (x + y) as f32 * 0.05, dimensions),
+                Vec::new(),
+                SolverBoundaryConditions::default(),
+            )
+        };
+        let mut solver = build();
+        // one tile covering the whole domain is never skipped
+        let mut dense = build();
+        dense.velocity = TiledGrid::new(dimensions, 64, Pipes::default());
+        dense.active = ActiveTiles::new(dimensions, 64);
+        for _ in 0..100 {
+            solver.solve(&[]);
+            dense.solve(&[]);
+        }
+        assert!(solver.active.count() < 16);
+        assert!(solver.velocity.allocated_tiles() < 16);
+        for x in 0..64 {
+            for y in 0..64 {
+                assert_eq!(solver.water.get(x, y), dense.water.get(x, y));
+            }
+        }
+    }
+    #[test]
+    fn edges_keep_tiles_free() {
+        // pool in one corner that never reaches the far edge, sponge level is below the ground
+        // so no water flows in
+        let dimensions = Vector2::new(64, 64);
+        let allocated = |x_plus| {
+            let mut solver = PipeSolver::new(
+                Grid::from_fn(|x, y| if x < 8 && y < 8 { 2.0 } else { 0.0 }, dimensions),
+                Grid::from_fn(|x, y| (x + y) as f32 * 0.05, dimensions),
+                Vec::new(),
+                SolverBoundaryConditions {
+                    x_plus,
+                    ..Default::default()
+                },
+            );
+            for _ in 0..100 {
+                solver.solve(&[]);
+            }
+            solver.velocity.allocated_tiles()
+        };
+        let wall = allocated(BoundaryConditions::Reflect);
+        assert!(wall < 16);
+        let sponge = BoundaryConditions::Sponge {
+            width: 8,
+            level: 0.0,
+        };
+        assert_eq!(allocated(sponge), wall);
+        assert_eq!(allocated(BoundaryConditions::FreeOutflow), wall);
+    }
+    /// surface height at probes x = 120..180 for each timestep of a channel n cells long with a
+    /// wave maker at x_minus
+    fn wave_channel(x_plus: BoundaryConditions, n: usize, steps: usize) -> Vec<Vec<f32>> {
+        use crate::TimeSeries;
+        let dimensions = Vector2::new(n, 1);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|_, _| 1.0, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            Vec::new(),
+            SolverBoundaryConditions {
+                x_minus: BoundaryConditions::Tide {
+                    level: TimeSeries::Sinusoid {
+                        mean: 1.0,
+                        amplitude: 0.01,
+                        period: 100.0,
+                    },
+                },
+                x_plus,
+                ..Default::default()
+            },
+        );
+        (0..steps)
+            .map(|_| {
+                solver.solve(&[]);
+                (120..180).map(|x| solver.water.get(x, 0) - 1.0).collect()
+            })
+            .collect()
+    }
+    #[test]
+    fn sponge_reflection() {
+        // Reflected wave is found by subtracting a run in a channel long enough that nothing
+        // comes back during the measurement. Measurement starts once the reflection reaches the
+        // probes and ends before it is reflected again by the wave maker
+        let n = 300;
+        let speed =
+            (PipeSolver::G * PipeSolver::L_X * PipeSolver::L_Y).sqrt() * PipeSolver::DELTA_T;
+        let start = (1.5 * n as f32 / speed) as usize;
+        let end = (2.4 * n as f32 / speed) as usize;
+        let reference = wave_channel(BoundaryConditions::Reflect, 3 * n, end);
+        let reflection_coefficient = |x_plus| {
+            let channel = wave_channel(x_plus, n, end);
+            let mut reflected = 0.0f32;
+            let mut incident = 0.0f32;
+            for t in start..end {
+                for (h, h_reference) in channel[t].iter().zip(reference[t].iter()) {
+                    reflected = reflected.max((h - h_reference).abs());
+                    incident = incident.max(h_reference.abs());
+                }
+            }
+            reflected / incident
+        };
+        let wall = reflection_coefficient(BoundaryConditions::Reflect);
+        let sponge = reflection_coefficient(BoundaryConditions::Sponge {
+            width: 50,
+            level: 1.0,
+        });
+        assert!(wall > 0.5);
+        assert!(sponge < 0.05);
+    }
+    #[test]
+    fn flux_form_sources() {
+        use crate::TimeSeries;
+        let dimensions = Vector2::new(20, 20);
+        let mut solver = PipeSolver::new(
+            Grid::from_fn(|_, _| 0.5, dimensions),
+            Grid::from_fn(|_, _| 0.0, dimensions),
+            vec![
+                Source::Gaussian {
+                    center: Vector2::new(5.0, 5.0),
+                    sigma: 2.0,
+                    discharge: TimeSeries::Constant(3.0),
+                },
+                // sink draining faster than water can reach it
+                Source::Point {
+                    center: Vector2::new(15.0, 15.0),
+                    discharge: TimeSeries::Constant(-10.0),
+                },
+            ],
+            SolverBoundaryConditions::default(),
+        );
+        solver.set_source_form(SourceForm::Flux);
+        let volume = solver.volume();
+        for _ in 0..100 {
+            solver.solve(&[]);
+        }
+        let added = solver.mass_balance().sources;
+        assert!(added < 30.0);
+        assert!((solver.volume() - volume - added).abs() < 1e-2);
     }
 }
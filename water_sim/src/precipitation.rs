@@ -0,0 +1,50 @@
+use super::{Grid, MassBalance};
+use nalgebra::Vector2;
+/// Rain and evaporation over the domain. Rates are in mm per timestep and must have the same
+/// dimensions as the water
+#[derive(Clone)]
+pub struct Precipitation {
+    pub rain: Grid<f32>,
+    pub evaporation: Grid<f32>,
+}
+impl Precipitation {
+    /// number of mm in one unit of water height
+    const MM_PER_UNIT: f32 = 1000.0;
+    /// builds precipitation with the same rates everywhere
+    pub fn uniform(dimensions: Vector2<usize>, rain: f32, evaporation: f32) -> Self {
+        Self {
+            rain: Grid::from_fn(|_, _| rain, dimensions),
+            evaporation: Grid::from_fn(|_, _| evaporation, dimensions),
+        }
+    }
+    /// Rains onto water then evaporates it. Evaporation is limited to the water in the cell.
+    /// Volume added and removed is recorded in balance
+    pub fn apply(&self, water: &mut Grid<f32>, balance: &mut MassBalance) {
+        for x in 0..water.x() {
+            for y in 0..water.y() {
+                let rain = self.rain.get(x, y) / Self::MM_PER_UNIT;
+                let h = water.get(x, y) + rain;
+                let evaporated = (self.evaporation.get(x, y) / Self::MM_PER_UNIT).min(h.max(0.0));
+                *water.get_mut(x, y) = h - evaporated;
+                balance.rain += rain;
+                balance.evaporation += evaporated;
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn evaporation_limited_by_water() {
+        let dimensions = Vector2::new(4, 4);
+        let mut water = Grid::from_fn(|x, _| if x < 2 { 0.01 } else { 0.0 }, dimensions);
+        let precipitation = Precipitation::uniform(dimensions, 1.0, 3.0);
+        let mut balance = MassBalance::default();
+        precipitation.apply(&mut water, &mut balance);
+        assert!((water.get(0, 0) - 0.008).abs() < 1e-6);
+        assert_eq!(water.get(3, 3), 0.0);
+        assert!((balance.rain - 0.016).abs() < 1e-6);
+        assert!((balance.evaporation - 0.032).abs() < 1e-6);
+    }
+}
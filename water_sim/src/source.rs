@@ -1,33 +1,364 @@
-use super::Grid;
+use super::{Grid, TimeSeries};
 use nalgebra::Vector2;
 use std::f32::consts::PI;
-/// Water Source, dynamically adds droplet in order to create pretty waves
-pub struct Source {
-    /// center of source
-    pub center: Vector2<f32>,
-    /// radius of cone
-    pub radius: f32,
-    /// height of added cone
-    pub height: f32,
-    /// period in number of timesteps of pattern
-    pub period: f32,
+use std::ops::Range;
+/// Water Source, dynamically adds or removes water. Discharges are in volume per unit time and
+/// negative discharges remove water. Each cell has an area of one so volume is added directly
+/// to water height
+#[derive(Clone, Debug)]
+pub enum Source {
+    /// Pulsing cone, dynamically adds droplet in order to create pretty waves
+    Pulse {
+        /// center of source
+        center: Vector2<f32>,
+        /// radius of cone
+        radius: f32,
+        /// height of added cone
+        height: f32,
+        /// period in number of timesteps of pattern
+        period: f32,
+    },
+    /// spring or sink in the cell containing center
+    Point {
+        center: Vector2<f32>,
+        discharge: TimeSeries,
+    },
+    /// discharge spread over a gaussian with standard deviation sigma in cells
+    Gaussian {
+        center: Vector2<f32>,
+        sigma: f32,
+        discharge: TimeSeries,
+    },
+    /// discharge spread evenly over cells within half a cell of the line from start to end
+    Line {
+        start: Vector2<f32>,
+        end: Vector2<f32>,
+        discharge: TimeSeries,
+    },
+    /// source moved by (x, y) cells at each timestep, for example a boat
+    Moving {
+        x: TimeSeries,
+        y: TimeSeries,
+        source: Box<Source>,
+    },
+}
+/// How sources change the water
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SourceForm {
+    /// each source changes the water height directly before the flow is solved
+    #[default]
+    Height,
+    /// Volume of all sources is summed per cell and added with the flux update, so springs and
+    /// sinks in the same cell cancel before sinks are limited by the water in the cell. Volume
+    /// added is recorded in the mass balance
+    Flux,
 }
 impl Source {
-    pub fn change_h(&self, height: &mut Grid<f32>, t: u32) {
-        let t = t as f32;
-        let s = (2.0 * PI * t / self.period).sin();
+    /// number of standard deviations a gaussian source is cut off at
+    const GAUSSIAN_CUTOFF: f32 = 4.0;
+    /// changes height by source at timestep t, delta_t is the length of the timestep
+    pub fn change_h(&self, height: &mut Grid<f32>, t: u32, delta_t: f32) {
+        let dimensions = Vector2::new(height.x(), height.y());
+        self.for_each_cell(dimensions, t, delta_t, &mut |x, y, volume| {
+            Self::add(height, x, y, volume);
+        });
+    }
+    /// Calls f with volume added to each cell the source touches at timestep t. Negative volumes
+    /// remove water and are not limited
+    pub fn for_each_cell(
+        &self,
+        dimensions: Vector2<usize>,
+        t: u32,
+        delta_t: f32,
+        f: &mut impl FnMut(usize, usize, f32),
+    ) {
+        self.for_each_cell_offset(dimensions, t, delta_t, Vector2::new(0.0, 0.0), f);
+    }
+    fn for_each_cell_offset(
+        &self,
+        dimensions: Vector2<usize>,
+        t: u32,
+        delta_t: f32,
+        offset: Vector2<f32>,
+        f: &mut impl FnMut(usize, usize, f32),
+    ) {
+        let (min, max) = self.bounds(offset, t);
+        let (x_range, y_range) = (
+            clamp_range(min.x, max.x, dimensions.x),
+            clamp_range(min.y, max.y, dimensions.y),
+        );
+        match self {
+            Self::Pulse {
+                center,
+                radius,
+                height: cone_height,
+                period,
+            } => {
+                let center = center + offset;
+                let s = (2.0 * PI * t as f32 / period).sin();
 
-        for x in 0..height.x() {
-            for y in 0..height.y() {
-                let distance = ((x as f32 - self.center.x).powi(2)
-                    + (y as f32 - self.center.y).powi(2))
-                .sqrt();
-                let dh = if distance < self.radius {
-                    self.height * (self.radius - distance) / self.radius
-                } else {
-                    0.0
-                };
-                *height.get_mut(x, y) += s * dh / self.period;
+                for x in x_range {
+                    for y in y_range.clone() {
+                        let distance =
+                            ((x as f32 - center.x).powi(2) + (y as f32 - center.y).powi(2)).sqrt();
+                        if distance < *radius {
+                            f(
+                                x,
+                                y,
+                                s * cone_height * (radius - distance) / radius / period,
+                            );
+                        }
+                    }
+                }
+            }
+            Self::Point { center, discharge } => {
+                let cell = (center + offset).map(|c| c.round());
+                if cell.x >= 0.0
+                    && cell.y >= 0.0
+                    && (cell.x as usize) < dimensions.x
+                    && (cell.y as usize) < dimensions.y
+                {
+                    f(
+                        cell.x as usize,
+                        cell.y as usize,
+                        discharge.value(t) * delta_t,
+                    );
+                }
+            }
+            Self::Gaussian {
+                center,
+                sigma,
+                discharge,
+            } => {
+                let center = center + offset;
+                Self::spread(
+                    x_range,
+                    y_range,
+                    discharge.value(t) * delta_t,
+                    |x, y| {
+                        let distance_squared =
+                            (x as f32 - center.x).powi(2) + (y as f32 - center.y).powi(2);
+                        (-distance_squared / (2.0 * sigma * sigma)).exp()
+                    },
+                    f,
+                );
+            }
+            Self::Line {
+                start,
+                end,
+                discharge,
+            } => {
+                let (start, end) = (start + offset, end + offset);
+                Self::spread(
+                    x_range,
+                    y_range,
+                    discharge.value(t) * delta_t,
+                    |x, y| {
+                        let point = Vector2::new(x as f32, y as f32);
+                        if distance_to_segment(point, start, end) <= 0.5 {
+                            1.0
+                        } else {
+                            0.0
+                        }
+                    },
+                    f,
+                );
+            }
+            Self::Moving { x, y, source } => source.for_each_cell_offset(
+                dimensions,
+                t,
+                delta_t,
+                offset + Vector2::new(x.value(t), y.value(t)),
+                f,
+            ),
+        }
+    }
+    /// corners of box containing every cell touched by source at timestep t when moved by offset
+    fn bounds(&self, offset: Vector2<f32>, t: u32) -> (Vector2<f32>, Vector2<f32>) {
+        let (min, max) = match self {
+            Self::Pulse { center, radius, .. } => (
+                center - Vector2::new(*radius, *radius),
+                center + Vector2::new(*radius, *radius),
+            ),
+            Self::Point { center, .. } => {
+                // rounded after moving so the cell is whole
+                let cell = (center + offset).map(|c| c.round());
+                return (cell, cell);
+            }
+            Self::Gaussian { center, sigma, .. } => {
+                let cutoff = Self::GAUSSIAN_CUTOFF * sigma;
+                (
+                    center - Vector2::new(cutoff, cutoff),
+                    center + Vector2::new(cutoff, cutoff),
+                )
+            }
+            Self::Line { start, end, .. } => (
+                start.zip_map(end, f32::min) - Vector2::new(0.5, 0.5),
+                start.zip_map(end, f32::max) + Vector2::new(0.5, 0.5),
+            ),
+            Self::Moving { x, y, source } => {
+                return source.bounds(offset + Vector2::new(x.value(t), y.value(t)), t)
+            }
+        };
+        (min + offset, max + offset)
+    }
+    /// spreads volume over cells in proportion to weight
+    fn spread(
+        x_range: Range<usize>,
+        y_range: Range<usize>,
+        volume: f32,
+        weight: impl Fn(usize, usize) -> f32,
+        f: &mut impl FnMut(usize, usize, f32),
+    ) {
+        let mut total = 0.0;
+        for x in x_range.clone() {
+            for y in y_range.clone() {
+                total += weight(x, y);
+            }
+        }
+        if total <= 0.0 {
+            return;
+        }
+        for x in x_range {
+            for y in y_range.clone() {
+                let w = weight(x, y);
+                if w > 0.0 {
+                    f(x, y, volume * w / total);
+                }
+            }
+        }
+    }
+    /// Adds sources to water in flux form and returns the net volume added. Volumes of all
+    /// sources are summed before being added so only the net removal is limited
+    pub(crate) fn apply_flux(
+        sources: &[Source],
+        water: &mut Grid<f32>,
+        t: u32,
+        delta_t: f32,
+    ) -> f32 {
+        let dimensions = Vector2::new(water.x(), water.y());
+        let mut volumes = Grid::from_fn(|_, _| 0.0, dimensions);
+        for source in sources.iter() {
+            source.for_each_cell(dimensions, t, delta_t, &mut |x, y, volume| {
+                *volumes.get_mut(x, y) += volume
+            });
+        }
+        let mut added = 0.0;
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                let volume = volumes.get(x, y);
+                if volume != 0.0 {
+                    added += Self::add(water, x, y, volume);
+                }
+            }
+        }
+        added
+    }
+    /// adds volume to cell, sinks can not remove more water than the cell holds. Returns volume
+    /// added
+    pub(crate) fn add(height: &mut Grid<f32>, x: usize, y: usize, volume: f32) -> f32 {
+        let h = height.get_mut(x, y);
+        let added = volume.max(-h.max(0.0));
+        *h += added;
+        added
+    }
+}
+/// cells from min to max inclusive that are inside of 0..len
+pub(crate) fn clamp_range(min: f32, max: f32, len: usize) -> Range<usize> {
+    let start = min.ceil().max(0.0) as usize;
+    let end = (max.floor() + 1.0).max(0.0) as usize;
+    start.min(len)..end.min(len)
+}
+/// distance from point to the closest point on the line from start to end
+fn distance_to_segment(point: Vector2<f32>, start: Vector2<f32>, end: Vector2<f32>) -> f32 {
+    let line = end - start;
+    let length_squared = line.norm_squared();
+    let along = if length_squared > 0.0 {
+        ((point - start).dot(&line) / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (point - (start + along * line)).norm()
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn discharge() {
+        let dimensions = Vector2::new(20, 20);
+        let sources = [
+            Source::Gaussian {
+                center: Vector2::new(10.0, 10.0),
+                sigma: 2.0,
+                discharge: TimeSeries::Constant(4.0),
+            },
+            Source::Line {
+                start: Vector2::new(2.0, 3.0),
+                end: Vector2::new(15.0, 12.0),
+                discharge: TimeSeries::Constant(4.0),
+            },
+            Source::Moving {
+                x: TimeSeries::Constant(5.0),
+                y: TimeSeries::Constant(0.0),
+                source: Box::new(Source::Point {
+                    center: Vector2::new(2.0, 2.0),
+                    discharge: TimeSeries::Constant(4.0),
+                }),
+            },
+        ];
+        for source in sources.iter() {
+            let mut height = Grid::from_fn(|_, _| 1.0, dimensions);
+            source.change_h(&mut height, 0, 0.5);
+            assert!((height.sum() - 402.0).abs() < 1e-3);
+        }
+        let mut height = Grid::from_fn(|_, _| 1.0, dimensions);
+        sources[2].change_h(&mut height, 0, 0.5);
+        assert_eq!(height.get(7, 2), 3.0);
+        // moved part way across a cell into the nearest one
+        let moving = Source::Moving {
+            x: TimeSeries::Constant(5.3),
+            y: TimeSeries::Constant(-0.6),
+            source: Box::new(Source::Point {
+                center: Vector2::new(2.0, 2.0),
+                discharge: TimeSeries::Constant(4.0),
+            }),
+        };
+        let mut height = Grid::from_fn(|_, _| 1.0, dimensions);
+        moving.change_h(&mut height, 0, 0.5);
+        assert!((height.sum() - 402.0).abs() < 1e-3);
+        assert_eq!(height.get(7, 1), 3.0);
+    }
+    #[test]
+    fn sink_limited_by_water() {
+        let mut height = Grid::from_fn(|_, _| 1.0, Vector2::new(5, 5));
+        let sink = Source::Point {
+            center: Vector2::new(2.0, 2.0),
+            discharge: TimeSeries::Constant(-3.0),
+        };
+        sink.change_h(&mut height, 0, 1.0);
+        assert_eq!(height.get(2, 2), 0.0);
+        assert_eq!(height.get(1, 2), 1.0);
+    }
+    #[test]
+    fn pulse_bounds() {
+        let dimensions = Vector2::new(30, 30);
+        // partly outside of the grid
+        let (center, radius, cone_height, period) = (Vector2::new(3.5, 20.0), 6.0, 2.0, 50.0);
+        let pulse = Source::Pulse {
+            center,
+            radius,
+            height: cone_height,
+            period,
+        };
+        let mut height = Grid::from_fn(|_, _| 1.0, dimensions);
+        pulse.change_h(&mut height, 10, 0.1);
+        let s = (2.0 * PI * 10.0 / period).sin();
+        for x in 0..dimensions.x {
+            for y in 0..dimensions.y {
+                let distance =
+                    ((x as f32 - center.x).powi(2) + (y as f32 - center.y).powi(2)).sqrt();
+                let dh = (radius - distance).max(0.0) * cone_height / radius;
+                assert!((height.get(x, y) - 1.0 - s * dh / period).abs() < 1e-6);
             }
         }
     }
@@ -0,0 +1,78 @@
+use super::{BoundaryConditions, Edge, Grid, SolverBoundaryConditions};
+use nalgebra::Vector2;
+/// Absorbing layer next to sponge boundaries. Inside the layer flow and surface are relaxed
+/// towards still water, the damping grows quadratically towards the edge so that waves are
+/// slowed down gradually instead of reflecting off of the start of the layer
+pub(crate) struct SpongeLayer {
+    /// damping at each cell, between 0 and 1
+    strength: Grid<f32>,
+    /// still water level each cell relaxes towards
+    level: Grid<f32>,
+}
+impl SpongeLayer {
+    /// damping rate per unit time at the edge of the layer
+    const MAX_DAMPING: f32 = 0.5;
+    /// builds layer from boundary conditions, None if there are no sponge boundaries
+    pub fn new(
+        boundary_conditions: &SolverBoundaryConditions,
+        dimensions: Vector2<usize>,
+    ) -> Option<Self> {
+        let mut strength = Grid::from_fn(|_, _| 0.0, dimensions);
+        let mut level = Grid::from_fn(|_, _| 0.0, dimensions);
+        let mut has_sponge = false;
+        for edge in Edge::ALL {
+            for i in 0..edge.len(dimensions.x, dimensions.y) {
+                let (width, sponge_level) = match boundary_conditions.at(edge, i) {
+                    BoundaryConditions::Sponge { width, level } => (*width, *level),
+                    _ => continue,
+                };
+                has_sponge = true;
+                let extent = match edge {
+                    Edge::XPlus | Edge::XMinus => dimensions.x,
+                    Edge::YPlus | Edge::YMinus => dimensions.y,
+                };
+                for depth in 0..width.min(extent) {
+                    let (x, y) = Self::inward(edge, dimensions, i, depth);
+                    let cell_strength = ((width - depth) as f32 / width as f32).powi(2);
+                    if cell_strength > strength.get(x, y) {
+                        *strength.get_mut(x, y) = cell_strength;
+                        *level.get_mut(x, y) = sponge_level;
+                    }
+                }
+            }
+        }
+        if has_sponge {
+            Some(Self { strength, level })
+        } else {
+            None
+        }
+    }
+    /// cell depth cells in from cell i along edge
+    fn inward(edge: Edge, dimensions: Vector2<usize>, i: usize, depth: usize) -> (usize, usize) {
+        match edge {
+            Edge::XPlus => (dimensions.x - 1 - depth, i),
+            Edge::XMinus => (depth, i),
+            Edge::YPlus => (i, dimensions.y - 1 - depth),
+            Edge::YMinus => (i, depth),
+        }
+    }
+    /// fraction of flow kept after timestep delta_t at cell (x, y)
+    pub fn damping(&self, x: usize, y: usize, delta_t: f32) -> f32 {
+        (-Self::MAX_DAMPING * self.strength.get(x, y) * delta_t).exp()
+    }
+    /// relaxes water surface towards still water level, water is the depth above ground
+    pub fn damp_surface(&self, water: &mut Grid<f32>, ground: &Grid<f32>, delta_t: f32) {
+        for x in 0..water.x() {
+            for y in 0..water.y() {
+                if self.strength.get(x, y) <= 0.0 {
+                    continue;
+                }
+                let g_h = ground.get(x, y);
+                let level = self.level.get(x, y);
+                let surface = water.get(x, y) + g_h;
+                let damped = level + (surface - level) * self.damping(x, y, delta_t);
+                *water.get_mut(x, y) = (damped - g_h).max(0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use super::{Edge, Grid, Tracer};
+/// Temperature carried with the water. Warm water is lighter so a column of it exerts less
+/// pressure than a column of cold water of the same depth, which drives density currents.
+/// Water added by sources and boundaries comes in at the reference temperature
+#[derive(Clone)]
+pub struct Temperature {
+    /// temperature at which the density of water is one
+    pub reference: f32,
+    /// fraction the density drops by per degree above the reference
+    pub expansion: f32,
+    /// heat above the reference carried with the water, the excess temperature times depth
+    excess: Tracer,
+}
+impl Temperature {
+    /// builds temperature from the temperature of the water in each cell, diffusion is as in
+    /// Tracer
+    pub fn new(
+        temperature: &Grid<f32>,
+        water: &Grid<f32>,
+        reference: f32,
+        expansion: f32,
+        diffusion: f32,
+    ) -> Self {
+        let mut excess = temperature.clone();
+        for x in 0..excess.x() {
+            for y in 0..excess.y() {
+                *excess.get_mut(x, y) = (temperature.get(x, y) - reference) * water.get(x, y);
+            }
+        }
+        Self {
+            reference,
+            expansion,
+            excess: Tracer::from_mass(excess, diffusion, 0.0, [1.0, 1.0, 1.0]),
+        }
+    }
+    /// temperature of the water at cell, dry cells are at the reference
+    pub fn temperature(&self, water: &Grid<f32>, x: usize, y: usize) -> f32 {
+        if water.get(x, y) <= 0.0 {
+            self.reference
+        } else {
+            self.reference + self.excess.concentration(water, x, y)
+        }
+    }
+    /// density of the water at cell relative to water at the reference temperature
+    pub fn density(&self, water: &Grid<f32>, x: usize, y: usize) -> f32 {
+        1.0 - self.expansion * (self.temperature(water, x, y) - self.reference)
+    }
+    /// Difference in pressure head from cell a to cell b that drives flow from a to b. With
+    /// uniform density it is the difference in surface height, otherwise the surface difference
+    /// is weighted by the mean density and the difference in density adds half of the mean
+    /// depth's worth of head
+    pub(crate) fn head_difference(
+        &self,
+        a: (usize, usize),
+        b: (usize, usize),
+        water: &Grid<f32>,
+        ground: &Grid<f32>,
+    ) -> f32 {
+        let (density_a, density_b) = (self.density(water, a.0, a.1), self.density(water, b.0, b.1));
+        let (depth_a, depth_b) = (water.get(a.0, a.1), water.get(b.0, b.1));
+        let surface = (depth_a + ground.get(a.0, a.1)) - (depth_b + ground.get(b.0, b.1));
+        (density_a + density_b) / 2.0 * surface
+            + (depth_a + depth_b) / 4.0 * (density_a - density_b)
+    }
+    /// moves heat with the water and lets it diffuse, arguments are as in Tracer::apply
+    pub(crate) fn transport(
+        &mut self,
+        water: &Grid<f32>,
+        delta_t: f32,
+        outflow: impl Fn(usize, usize, Edge) -> f32,
+        neighbour: impl Fn(usize, usize, Edge) -> Option<(usize, usize)>,
+    ) {
+        self.excess.apply(water, delta_t, outflow, neighbour);
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    use nalgebra::Vector2;
+    #[test]
+    fn warm_water_is_lighter() {
+        let dimensions = Vector2::new(2, 1);
+        let ground = Grid::from_fn(|_, _| 0.0, dimensions);
+        let water = Grid::from_fn(|_, _| 1.0, dimensions);
+        let temperature = Temperature::new(
+            &Grid::from_vec(dimensions, vec![20.0, 10.0]),
+            &water,
+            10.0,
+            0.01,
+            0.0,
+        );
+        assert!((temperature.temperature(&water, 0, 0) - 20.0).abs() < 1e-5);
+        assert!((temperature.density(&water, 0, 0) - 0.9).abs() < 1e-5);
+        // cold water pushes under the warm water at the same level
+        assert!(temperature.head_difference((1, 0), (0, 0), &water, &ground) > 0.0);
+        // a warm column of the same bottom pressure stands higher and spreads over the cold
+        let balanced = Grid::from_vec(dimensions, vec![1.0 / 0.9, 1.0]);
+        let temperature = Temperature::new(
+            &Grid::from_vec(dimensions, vec![20.0, 10.0]),
+            &balanced,
+            10.0,
+            0.01,
+            0.0,
+        );
+        assert!(temperature.head_difference((0, 0), (1, 0), &balanced, &ground) > 0.0);
+    }
+}
@@ -0,0 +1,52 @@
+use std::{f32::consts::PI, sync::Arc};
+/// Value that changes over time, times are in timesteps
+#[derive(Clone, Debug)]
+pub enum TimeSeries {
+    Constant(f32),
+    Sinusoid {
+        mean: f32,
+        amplitude: f32,
+        period: f32,
+    },
+    /// (time, value) pairs sorted by time, linearly interpolated between entries and held
+    /// constant before the first and after the last entry
+    Table(Arc<[(f32, f32)]>),
+}
+impl TimeSeries {
+    /// value at timestep t
+    pub fn value(&self, t: u32) -> f32 {
+        let t = t as f32;
+        match self {
+            Self::Constant(value) => *value,
+            Self::Sinusoid {
+                mean,
+                amplitude,
+                period,
+            } => mean + amplitude * (2.0 * PI * t / period).sin(),
+            Self::Table(table) => {
+                let after = table.iter().position(|(time, _)| *time > t);
+                match after {
+                    Some(0) => table[0].1,
+                    Some(idx) => {
+                        let (t0, v0) = table[idx - 1];
+                        let (t1, v1) = table[idx];
+                        v0 + (v1 - v0) * (t - t0) / (t1 - t0)
+                    }
+                    None => table.last().map(|(_, value)| *value).unwrap_or(0.0),
+                }
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn table() {
+        let series = TimeSeries::Table(Arc::new([(10.0, 1.0), (20.0, 3.0), (40.0, 0.0)]));
+        assert_eq!(series.value(0), 1.0);
+        assert!((series.value(15) - 2.0).abs() < 1e-6);
+        assert!((series.value(30) - 1.5).abs() < 1e-6);
+        assert_eq!(series.value(50), 0.0);
+    }
+}
@@ -0,0 +1,219 @@
+use super::{Edge, Grid};
+use nalgebra::Vector2;
+/// Passive scalar carried by the water such as dye or a pollutant. The tracer does not change
+/// the flow, it is moved along with it, spreads out by diffusion and decays over time
+#[derive(Clone)]
+pub struct Tracer {
+    /// fraction of the difference in concentration between neighbouring cells exchanged per
+    /// unit time
+    pub diffusion: f32,
+    /// fraction of the tracer that decays per unit time
+    pub decay: f32,
+    /// rgb colour water is tinted where the tracer is concentrated
+    pub color: [f32; 3],
+    /// amount of tracer in each cell
+    mass: Grid<f32>,
+    /// amount of tracer carried out of the domain
+    outflow: f32,
+}
+impl Tracer {
+    /// builds tracer with none in the water yet
+    pub fn new(dimensions: Vector2<usize>, diffusion: f32, decay: f32, color: [f32; 3]) -> Self {
+        Self {
+            diffusion,
+            decay,
+            color,
+            mass: Grid::from_fn(|_, _| 0.0, dimensions),
+            outflow: 0.0,
+        }
+    }
+    /// builds tracer from the amount in each cell
+    pub(crate) fn from_mass(mass: Grid<f32>, diffusion: f32, decay: f32, color: [f32; 3]) -> Self {
+        Self {
+            diffusion,
+            decay,
+            color,
+            mass,
+            outflow: 0.0,
+        }
+    }
+    /// amount of tracer in each cell
+    pub fn mass(&self) -> &Grid<f32> {
+        &self.mass
+    }
+    /// amount of tracer per depth of water at cell
+    pub fn concentration(&self, water: &Grid<f32>, x: usize, y: usize) -> f32 {
+        self.mass.get(x, y) / water.get(x, y).max(f32::EPSILON)
+    }
+    /// total amount of tracer in the water
+    pub fn total(&self) -> f32 {
+        self.mass.sum()
+    }
+    /// amount of tracer carried out of the domain so far
+    pub fn outflow(&self) -> f32 {
+        self.outflow
+    }
+    /// adds amount of tracer spread evenly over the cells of a disk inside of the domain
+    pub fn inject(&mut self, center: Vector2<f32>, radius: f32, amount: f32) {
+        let radius = radius.max(0.5);
+        let r = radius.ceil() as i32;
+        let (c_x, c_y) = (center.x.round() as i32, center.y.round() as i32);
+        let mut cells = vec![];
+        for x in c_x - r..=c_x + r {
+            for y in c_y - r..=c_y + r {
+                if x < 0 || y < 0 || x >= self.mass.x() as i32 || y >= self.mass.y() as i32 {
+                    continue;
+                }
+                if Vector2::new(x as f32 - center.x, y as f32 - center.y).norm() <= radius {
+                    cells.push((x as usize, y as usize));
+                }
+            }
+        }
+        let per_cell = amount / cells.len().max(1) as f32;
+        for (x, y) in cells {
+            *self.mass.get_mut(x, y) += per_cell;
+        }
+    }
+    /// Moves the tracer with the water then lets it diffuse and decay, arguments are as in
+    /// advect
+    pub(crate) fn apply(
+        &mut self,
+        water: &Grid<f32>,
+        delta_t: f32,
+        outflow: impl Fn(usize, usize, Edge) -> f32,
+        neighbour: impl Fn(usize, usize, Edge) -> Option<(usize, usize)>,
+    ) {
+        self.outflow += advect(&mut self.mass, water, delta_t, outflow, &neighbour);
+        self.diffuse(water, delta_t, &neighbour);
+        let remaining = (1.0 - self.decay * delta_t).max(0.0);
+        for x in 0..self.mass.x() {
+            for y in 0..self.mass.y() {
+                *self.mass.get_mut(x, y) *= remaining;
+            }
+        }
+    }
+    /// Exchanges tracer between neighbouring cells in proportion to the difference in
+    /// concentration, limited by the shallower side so nothing diffuses onto dry ground
+    fn diffuse(
+        &mut self,
+        water: &Grid<f32>,
+        delta_t: f32,
+        neighbour: impl Fn(usize, usize, Edge) -> Option<(usize, usize)>,
+    ) {
+        // more than a quarter to each of four neighbours would overshoot
+        let rate = (self.diffusion * delta_t).min(0.25);
+        if rate <= 0.0 {
+            return;
+        }
+        let mut new_mass = self.mass.clone();
+        for x in 0..self.mass.x() {
+            for y in 0..self.mass.y() {
+                // each pair of cells once
+                for edge in [Edge::XPlus, Edge::YPlus] {
+                    if let Some((n_x, n_y)) = neighbour(x, y, edge) {
+                        let depth = water.get(x, y).min(water.get(n_x, n_y));
+                        if depth <= 0.0 {
+                            continue;
+                        }
+                        let difference =
+                            self.concentration(water, x, y) - self.concentration(water, n_x, n_y);
+                        let exchanged = rate * difference * depth;
+                        *new_mass.get_mut(x, y) -= exchanged;
+                        *new_mass.get_mut(n_x, n_y) += exchanged;
+                    }
+                }
+            }
+        }
+        self.mass = new_mass;
+    }
+}
+/// Moves mass suspended in the water with the flow, returning the mass carried out of the
+/// domain. outflow is the flux leaving cell (x, y) towards edge and neighbour is the cell on
+/// the other side, None if it is outside of the domain. Mass leaves at the concentration of
+/// the cell it comes from, water is the depth the flux is taken from. Mass may be negative
+/// for quantities measured from a reference
+pub(crate) fn advect(
+    mass: &mut Grid<f32>,
+    water: &Grid<f32>,
+    delta_t: f32,
+    outflow: impl Fn(usize, usize, Edge) -> f32,
+    neighbour: impl Fn(usize, usize, Edge) -> Option<(usize, usize)>,
+) -> f32 {
+    let mut left_domain = 0.0;
+    let mut new_mass = mass.clone();
+    for x in 0..water.x() {
+        for y in 0..water.y() {
+            let cell_mass = mass.get(x, y);
+            if cell_mass == 0.0 {
+                continue;
+            }
+            let fluxes = Edge::ALL.map(|edge| outflow(x, y, edge).max(0.0));
+            let total: f32 = fluxes.iter().sum();
+            if total <= 0.0 {
+                continue;
+            }
+            // no more can leave than the cell holds
+            let fraction = (total * delta_t / water.get(x, y).max(f32::EPSILON)).min(1.0);
+            let leaving = cell_mass * fraction;
+            for (edge, flux) in Edge::ALL.iter().zip(fluxes) {
+                let moved = leaving * flux / total;
+                *new_mass.get_mut(x, y) -= moved;
+                match neighbour(x, y, *edge) {
+                    Some((n_x, n_y)) => *new_mass.get_mut(n_x, n_y) += moved,
+                    None => left_domain += moved,
+                }
+            }
+        }
+    }
+    *mass = new_mass;
+    left_domain
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn spill_spreads_and_decays() {
+        let dimensions = Vector2::new(20, 20);
+        let water = Grid::from_fn(|x, _| if x < 15 { 1.0 } else { 0.0 }, dimensions);
+        let neighbour =
+            |x: usize, y: usize, edge: Edge| edge.neighbour(dimensions.x, dimensions.y, x, y);
+        let still = |_: usize, _: usize, _: Edge| 0.0;
+        let mut tracer = Tracer::new(dimensions, 1.0, 0.0, [1.0, 0.0, 0.0]);
+        tracer.inject(Vector2::new(10.0, 10.0), 2.0, 10.0);
+        assert!((tracer.total() - 10.0).abs() < 1e-4);
+        for _ in 0..500 {
+            tracer.apply(&water, 0.1, still, neighbour);
+        }
+        assert!((tracer.total() - 10.0).abs() < 1e-3);
+        assert!(tracer.mass().get(0, 0) > 0.0);
+        // no tracer on dry ground
+        assert_eq!(tracer.mass().get(17, 10), 0.0);
+
+        let mut decaying = Tracer::new(dimensions, 0.0, 0.1, [1.0, 0.0, 0.0]);
+        decaying.inject(Vector2::new(10.0, 10.0), 0.0, 1.0);
+        decaying.apply(&water, 1.0, still, neighbour);
+        assert!((decaying.total() - 0.9).abs() < 1e-5);
+    }
+    #[test]
+    fn advected_downstream() {
+        let dimensions = Vector2::new(10, 1);
+        let water = Grid::from_fn(|_, _| 1.0, dimensions);
+        let mut mass = Grid::from_fn(|x, _| if x == 0 { 1.0 } else { 0.0 }, dimensions);
+        let outflow = |_: usize, _: usize, edge: Edge| match edge {
+            Edge::XPlus => 1.0,
+            _ => 0.0,
+        };
+        let neighbour =
+            |x: usize, y: usize, edge: Edge| edge.neighbour(dimensions.x, dimensions.y, x, y);
+        let mut left_domain = 0.0;
+        for _ in 0..50 {
+            left_domain += advect(&mut mass, &water, 0.1, outflow, neighbour);
+        }
+        assert!(mass.get(5, 0) > mass.get(0, 0));
+        let mut sum = left_domain;
+        for x in 0..dimensions.x {
+            sum += mass.get(x, 0);
+        }
+        assert!((sum - 1.0).abs() < 1e-5);
+    }
+}
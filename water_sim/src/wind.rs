@@ -0,0 +1,95 @@
+use super::Grid;
+use nalgebra::Vector2;
+/// Wind velocity over the water surface
+#[derive(Clone)]
+pub enum WindField {
+    /// same wind over whole domain
+    Uniform(Vector2<f32>),
+    /// wind per cell, must have same dimensions as water
+    Grid(Grid<Vector2<f32>>),
+}
+/// Scales wind over time
+#[derive(Clone, Copy, Debug)]
+pub enum WindEnvelope {
+    /// Wind is always at full strength
+    Constant,
+    /// Ramps linearly from nothing at start to full strength at peak_start, holds until peak_end
+    /// then ramps back down to nothing at end. All times are in timesteps
+    Storm {
+        start: u32,
+        peak_start: u32,
+        peak_end: u32,
+        end: u32,
+    },
+}
+impl WindEnvelope {
+    /// strength of wind at timestep t, between 0 and 1
+    pub fn strength(&self, t: u32) -> f32 {
+        match *self {
+            Self::Constant => 1.0,
+            Self::Storm {
+                start,
+                peak_start,
+                peak_end,
+                end,
+            } => {
+                if t <= start || t >= end {
+                    0.0
+                } else if t < peak_start {
+                    (t - start) as f32 / (peak_start - start) as f32
+                } else if t <= peak_end {
+                    1.0
+                } else {
+                    (end - t) as f32 / (end - peak_end) as f32
+                }
+            }
+        }
+    }
+}
+/// Wind forcing, applies surface stress to the water
+#[derive(Clone)]
+pub struct Wind {
+    pub field: WindField,
+    pub envelope: WindEnvelope,
+    /// density of air times drag coefficient divided by density of water
+    pub drag: f32,
+}
+impl Wind {
+    /// drag for air over water using a drag coefficient of 1.3e-3
+    pub const AIR_WATER_DRAG: f32 = 1.2 * 1.3e-3 / 1000.0;
+    /// depth water column is clamped to so stress does not blow up in shallow cells
+    const MIN_DEPTH: f32 = 0.01;
+    /// wind velocity at cell (x, y) at timestep t
+    pub fn velocity(&self, x: usize, y: usize, t: u32) -> Vector2<f32> {
+        let wind = match &self.field {
+            WindField::Uniform(wind) => *wind,
+            WindField::Grid(grid) => grid.get(x, y),
+        };
+        self.envelope.strength(t) * wind
+    }
+    /// acceleration given to water column of depth h by wind velocity
+    pub fn acceleration(&self, wind: Vector2<f32>, h: f32) -> Vector2<f32> {
+        if h <= 0.0 {
+            return Vector2::new(0.0, 0.0);
+        }
+        self.drag * wind.norm() * wind / h.max(Self::MIN_DEPTH)
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[test]
+    fn storm_envelope() {
+        let storm = WindEnvelope::Storm {
+            start: 10,
+            peak_start: 20,
+            peak_end: 30,
+            end: 50,
+        };
+        assert_eq!(storm.strength(0), 0.0);
+        assert!((storm.strength(15) - 0.5).abs() < 1e-6);
+        assert_eq!(storm.strength(25), 1.0);
+        assert!((storm.strength(40) - 0.5).abs() < 1e-6);
+        assert_eq!(storm.strength(60), 0.0);
+    }
+}